@@ -37,6 +37,8 @@ mod star_thirty_four;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
+    let plain = args.iter().any(|arg| arg == "--plain");
+    let params: Vec<&str> = args.iter().skip(2).map(|arg| arg.as_str()).filter(|arg| !arg.starts_with("--")).collect();
     match args[1].as_str() {
         "1" => star_one::run(),
         "2" => star_two::run(),
@@ -45,18 +47,59 @@ fn main() {
         "5" => star_five::run(),
         "6" => star_six::run(),
         "7" => star_seven::run(),
+        "7-render" => star_seven::run_render(plain),
         "8" => star_eight::run(),
+        "8-render" => star_eight::run_render(plain),
         "9" => star_nine::run(),
+        "9-report" => star_nine::run_report(),
+        "9-dot" => star_nine::run_dot(params.first().copied()),
         "10" => star_ten::run(),
+        "10-bench" => star_ten::run_bench(),
         "11" => star_eleven::run(),
+        "11-path" => star_eleven::run_path(),
+        "11-animate" => star_eleven::run_animation(
+            params.first().and_then(|arg| arg.parse().ok()).unwrap_or(100),
+            !plain,
+        ),
+        "11-multi" => star_eleven::run_multi(params.first().copied().unwrap_or("right")),
         "12" => star_twelve::run(),
+        "12-loops" => star_twelve::run_loops(params.first().copied()),
         "13" => star_thirteen::run(),
+        "13-precedence" => star_thirteen::run_precedence(),
         "14" => star_fourteen::run(),
+        "14-bench" => star_fourteen::run_bench(),
+        "14-ops" => star_fourteen::run_ops(params.first().copied().unwrap_or("+,*,||")),
+        "14-explain" => star_fourteen::run_explain(
+            params.first().copied().unwrap_or("one"),
+            params.get(1).copied().unwrap_or("+,*,||"),
+        ),
+        "14-precedence" => star_fourteen::run_precedence(params.first().copied().unwrap_or("+,*,||")),
         "15" => star_fifteen::run(),
+        "15-report" => star_fifteen::run_report(plain),
         "16" => star_sixteen::run(),
+        "16-report" => star_sixteen::run_report(plain),
+        "16-lattice" => star_sixteen::run_lattice(),
+        "16-optimize" => star_sixteen::run_optimize(
+            params.first().and_then(|arg| arg.chars().next()).unwrap_or('0'),
+            params.get(1).and_then(|arg| arg.parse().ok()).unwrap_or(1),
+            params.get(2).copied().unwrap_or("max"),
+            params.get(3).copied().unwrap_or("harmonics"),
+            plain,
+        ),
         "17" => star_seventeen::run(),
+        "17-bench" => star_seventeen::run_bench(),
         "18" => star_eighteen::run(),
+        "18-bench" => star_eighteen::run_bench(),
+        "18-render" => star_eighteen::run_render(
+            params.first().copied().unwrap_or("first"),
+            args.iter().any(|arg| arg == "--steps"),
+        ),
+        "18-convert" => star_eighteen::run_convert(params.first().copied().unwrap_or("commas")),
+        "18-strategies" => star_eighteen::run_strategies(
+            params.first().copied().unwrap_or("blocks,first,best,worst,fragments:2"),
+        ),
         "19" => star_nineteen::run(),
+        "19-bench" => star_nineteen::run_bench(),
         "20" => star_twenty::run(),
         "21" => star_twenty_one::run(),
         "22" => star_twenty_two::run(),
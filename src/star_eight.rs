@@ -1,5 +1,7 @@
 use std::fs;
 
+use crate::star_seven::{match_heatmap, render_heatmap, render_matches};
+
 const XMAS: &str = "MAS";
 
 pub fn run() {
@@ -8,6 +10,15 @@ pub fn run() {
     println!("Result: {}", result);
 }
 
+pub fn run_render(plain: bool) {
+    let file = fs::read_to_string("./inputs/star_eight.txt").unwrap();
+    let matches = find_matches(&file);
+    println!("{}", render_matches(&file, &matches, !plain));
+    println!();
+    println!("{}", render_heatmap(&match_heatmap(&file, &matches)));
+    println!("Result: {}", matches.len());
+}
+
 fn count_all(input: &String) -> usize {
     let mut count = 0;
     let w = input.lines().next().unwrap().len();
@@ -63,6 +74,31 @@ fn is_xmas(input: &String, x: usize, y: usize, w: usize, h: usize) -> bool {
 
 }
 
+fn find_matches(input: &str) -> Vec<Vec<(usize, usize)>> {
+    let mut matches = vec![];
+    let w = input.lines().next().unwrap().len();
+    let h = input.lines().count();
+
+    let mut input = input.to_string();
+    input.retain(|s| !s.is_whitespace());
+
+    for y in 0..h {
+        for x in 0..w {
+            if get_char_xy(&input, x, y, w) == "A" && is_xmas(&input, x, y, w, h) {
+                matches.push(vec![
+                    (x - 1, y - 1),
+                    (x,     y    ),
+                    (x + 1, y + 1),
+                    (x - 1, y + 1),
+                    (x + 1, y - 1),
+                ]);
+            }
+        }
+    }
+
+    matches
+}
+
 fn get_char_xy(input: &String, x: usize, y: usize, w: usize) -> String {
     input.chars().nth(x + (y * w)).unwrap().to_string()
 }
@@ -92,6 +128,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_find_matches() {
+        let input = [
+            "M.S.M",
+            ".A.A.",
+            "M.S.M",
+        ].join("\n");
+        assert_eq!(
+            find_matches(&input),
+            vec![
+                vec![(0, 0), (1, 1), (2, 2), (0, 2), (2, 0)],
+                vec![(2, 0), (3, 1), (4, 2), (2, 2), (4, 0)],
+            ],
+        );
+        assert_eq!(
+            render_matches(&input, &find_matches(&input), false),
+            input,
+        );
+        assert_eq!(
+            render_heatmap(&match_heatmap(&input, &find_matches(&input))),
+            [
+                "1.2.1",
+                ".1.1.",
+                "1.2.1",
+            ].join("\n"),
+        );
+    }
+
     #[test]
     fn test_is_xmas() {
         assert_eq!(
@@ -1,20 +1,541 @@
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+use std::fmt;
 use std::fs;
+use std::io::{self, Write};
+use std::time::Instant;
+
+use crate::star_seventeen::compact_blocks;
+#[cfg(test)]
+use crate::star_ten::Lcg;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct FileSpan {
+    pub id: usize,
+    pub start: usize,
+    pub len: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct FreeSpan {
+    pub start: usize,
+    pub len: usize,
+}
+
+// Files are kept in id order and free spans in disk order.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct DiskMap {
+    pub files: Vec<FileSpan>,
+    pub free: Vec<FreeSpan>,
+}
+
+impl DiskMap {
+    pub(crate) fn from_unpacked(unpacked_filesystem: &[(usize, Option<usize>)]) -> Result<DiskMap, DiskMapError> {
+        let mut disk = DiskMap { files: vec![], free: vec![] };
+        let mut start: usize = 0;
+        for &(len, id) in unpacked_filesystem {
+            match id {
+                Some(id) => disk.files.push(FileSpan { id, start, len }),
+                None => disk.free.push(FreeSpan { start, len }),
+            }
+            start = start.checked_add(len).ok_or(DiskMapError::DiskTooLarge)?;
+        }
+
+        Ok(disk)
+    }
+
+    fn size(&self) -> usize {
+        let file_end = self.files.iter().map(|file| file.start + file.len).max();
+        let free_end = self.free.iter().map(|span| span.start + span.len).max();
+        file_end.max(free_end).unwrap_or(0)
+    }
+}
+
+pub(crate) trait CompactionStrategy {
+    fn name(&self) -> String;
+    fn compact(&self, disk: &DiskMap) -> Vec<FileSpan>;
+}
+
+pub(crate) struct BlockByBlock;
+
+impl CompactionStrategy for BlockByBlock {
+    fn name(&self) -> String {
+        "blocks".to_string()
+    }
+
+    fn compact(&self, disk: &DiskMap) -> Vec<FileSpan> {
+        compact_blocks(disk)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Fit {
+    First,
+    Best,
+    Worst,
+}
+
+impl CompactionStrategy for Fit {
+    fn name(&self) -> String {
+        match self {
+            Fit::First => "first",
+            Fit::Best => "best",
+            Fit::Worst => "worst",
+        }.to_string()
+    }
+
+    fn compact(&self, disk: &DiskMap) -> Vec<FileSpan> {
+        compact_whole_files(disk, *self)
+    }
+}
+
+pub(crate) struct FragmentLimited {
+    pub max_fragments: usize,
+}
+
+impl CompactionStrategy for FragmentLimited {
+    fn name(&self) -> String {
+        format!("fragments:{}", self.max_fragments)
+    }
+
+    fn compact(&self, disk: &DiskMap) -> Vec<FileSpan> {
+        compact_fragments(disk, self.max_fragments)
+    }
+}
+
+pub(crate) fn parse_strategy(spec: &str) -> Option<Box<dyn CompactionStrategy>> {
+    match spec {
+        "blocks" => Some(Box::new(BlockByBlock)),
+        "first" => Some(Box::new(Fit::First)),
+        "best" => Some(Box::new(Fit::Best)),
+        "worst" => Some(Box::new(Fit::Worst)),
+        _ => {
+            let max_fragments = spec.strip_prefix("fragments:")?.parse().ok()
+                .filter(|&max_fragments| max_fragments > 0)?;
+            Some(Box::new(FragmentLimited { max_fragments }))
+        },
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct CompactionReport {
+    pub checksum: u128,
+    pub fragments: usize,
+    pub largest_free: usize,
+    pub moves: usize,
+}
+
+impl CompactionReport {
+    // Adjacent spans of the same file count as one fragment, and a span counts
+    // as a move when it no longer starts where its file started.
+    pub(crate) fn new(disk: &DiskMap, files: &[FileSpan]) -> CompactionReport {
+        let mut spans = files.to_vec();
+        spans.sort_by_key(|span| span.start);
+
+        let mut fragments = 0;
+        let mut largest_free = 0;
+        let mut end = 0;
+        let mut previous: Option<&FileSpan> = None;
+        for span in spans.iter().filter(|span| span.len > 0) {
+            largest_free = largest_free.max(span.start - end);
+            if previous.is_none_or(|previous| previous.id != span.id || span.start != end) {
+                fragments += 1;
+            }
+            end = span.start + span.len;
+            previous = Some(span);
+        }
+        largest_free = largest_free.max(disk.size().saturating_sub(end));
+
+        let moves = compaction_moves(disk, files).len();
+
+        CompactionReport { checksum: checksum_files(files), fragments, largest_free, moves }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct Move {
+    pub id: usize,
+    pub from: usize,
+    pub to: usize,
+    pub len: usize,
+}
+
+// Recovers the moves behind a compacted layout: every span that no longer
+// starts where its file started was moved there, highest id first and left to
+// right within a file, taking blocks from the end of what is left in place.
+pub(crate) fn compaction_moves(disk: &DiskMap, files: &[FileSpan]) -> Vec<Move> {
+    let mut moved: Vec<&FileSpan> = files.iter()
+        .filter(|span| span.len > 0 && disk.files[span.id].start != span.start)
+        .collect();
+    moved.sort_by_key(|span| (Reverse(span.id), span.start));
+
+    let mut remaining: Vec<usize> = disk.files.iter().map(|file| file.len).collect();
+    moved.iter()
+        .map(|span| {
+            remaining[span.id] -= span.len;
+            Move { id: span.id, from: disk.files[span.id].start + remaining[span.id], to: span.start, len: span.len }
+        })
+        .collect()
+}
+
+// Larger disks are not drawn, since every rendered line holds one
+// character per block.
+const MAX_RENDERED_BLOCKS: usize = 1_000_000;
+
+// Every step redraws the whole disk, so the steps are only printed while
+// moves times disk size stays below this.
+const MAX_TRACED_BLOCKS: usize = 10_000_000;
+
+// The layout in the puzzle's block notation, one character per block. Only
+// the last digit of each id fits in a block.
+pub(crate) struct BlockCanvas {
+    blocks: Vec<u8>,
+}
+
+impl BlockCanvas {
+    pub(crate) fn new(files: &[FileSpan], size: usize) -> Self {
+        let mut blocks = vec![b'.'; size];
+        for file in files {
+            blocks[file.start..file.start + file.len].fill(b'0' + (file.id % 10) as u8);
+        }
+
+        BlockCanvas { blocks }
+    }
+
+    pub(crate) fn apply(&mut self, mv: &Move) {
+        self.blocks[mv.from..mv.from + mv.len].fill(b'.');
+        self.blocks[mv.to..mv.to + mv.len].fill(b'0' + (mv.id % 10) as u8);
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.blocks).unwrap()
+    }
+}
+
+pub(crate) fn render_blocks(files: &[FileSpan], size: usize) -> String {
+    BlockCanvas::new(files, size).as_str().to_string()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct SerializeError {
+    pub start: usize,
+    pub len: usize,
+}
+
+impl fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "file of {} blocks at {} does not fit in one digit", self.len, self.start)
+    }
+}
+
+// Sizes of the runs in a layout, alternating file and gap as in a disk map.
+// The format numbers files by position, so every run of blocks becomes its own
+// file and ids come back renumbered left to right. Free space after the last
+// file is kept so the disk size survives the round trip.
+pub(crate) fn layout_sizes(files: &[FileSpan], size: usize) -> Vec<usize> {
+    let mut spans: Vec<&FileSpan> = files.iter().filter(|span| span.len > 0).collect();
+    spans.sort_by_key(|span| span.start);
+
+    let mut runs: Vec<FileSpan> = vec![];
+    for span in spans {
+        match runs.last_mut() {
+            Some(run) if run.id == span.id && run.start + run.len == span.start => run.len += span.len,
+            _ => runs.push(*span),
+        }
+    }
+
+    let mut sizes = vec![];
+    let mut end = 0;
+    for run in runs {
+        if !sizes.is_empty() || run.start > 0 {
+            push_free_run(&mut sizes, run.start - end);
+        }
+        sizes.push(run.len);
+        end = run.start + run.len;
+    }
+    if size > end {
+        push_free_run(&mut sizes, size - end);
+    }
+
+    sizes
+}
+
+fn push_free_run(sizes: &mut Vec<usize>, len: usize) {
+    if sizes.is_empty() {
+        sizes.push(0);
+    }
+    sizes.push(len);
+}
+
+// Empty files take no id, so gaps longer than 9 blocks are written as several
+// gaps separated by zero-length files. Longer files cannot be split that way.
+pub(crate) fn format_digits(sizes: &[usize]) -> Result<String, SerializeError> {
+    let mut disk_map = String::new();
+    let mut start = 0;
+    for (index, &len) in sizes.iter().enumerate() {
+        if index % 2 == 0 && len > 9 {
+            return Err(SerializeError { start, len });
+        }
+        let mut rest = len;
+        while rest > 9 {
+            disk_map.push_str("90");
+            rest -= 9;
+        }
+        disk_map.push(char::from_digit(rest as u32, 10).unwrap());
+        start += len;
+    }
+
+    Ok(disk_map)
+}
+
+// Comma-separated disk maps start with this header, so a map like "12" is
+// never mistaken for the digits 1 and 2.
+const COMMA_HEADER: &[u8] = b"commas:";
+
+pub(crate) fn format_commas(sizes: &[usize]) -> String {
+    let sizes: Vec<String> = sizes.iter().map(|size| size.to_string()).collect();
+    String::from_utf8_lossy(COMMA_HEADER).into_owned() + &sizes.join(",")
+}
+
+// Varint disk maps start with this header, followed by every size as an
+// unsigned LEB128 number: seven bits per byte, low bits first, with the top
+// bit set on every byte but the last.
+const VARINT_HEADER: &[u8] = b"DMAP";
+
+pub(crate) fn encode_varints(sizes: &[usize]) -> Vec<u8> {
+    let mut bytes = VARINT_HEADER.to_vec();
+    for &size in sizes {
+        let mut rest = size;
+        while rest >= 0x80 {
+            bytes.push((rest & 0x7f) as u8 | 0x80);
+            rest >>= 7;
+        }
+        bytes.push(rest as u8);
+    }
+
+    bytes
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) enum DiskMapError {
+    InvalidDigit { offset: usize, byte: u8 },
+    InvalidSize { index: usize, text: String },
+    TruncatedVarint { offset: usize },
+    VarintOverflow { offset: usize },
+    DiskTooLarge,
+}
+
+impl fmt::Display for DiskMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiskMapError::InvalidDigit { offset, byte } => write!(f, "byte {:#04x} at {} is not a digit", byte, offset),
+            DiskMapError::InvalidSize { index, text } => write!(f, "size {} is not a number: {:?}", index, text),
+            DiskMapError::TruncatedVarint { offset } => write!(f, "varint at {} is cut off", offset),
+            DiskMapError::VarintOverflow { offset } => write!(f, "varint at {} does not fit in usize", offset),
+            DiskMapError::DiskTooLarge => write!(f, "disk has more than {} blocks", usize::MAX),
+        }
+    }
+}
+
+// Reads the sizes of a disk map in any of the three formats: varints or
+// comma-separated sizes when the input starts with their header, and one digit
+// per size otherwise. Whitespace is ignored in the text formats.
+pub(crate) fn parse_disk_map(input: &[u8]) -> Result<Vec<usize>, DiskMapError> {
+    if let Some(bytes) = input.strip_prefix(VARINT_HEADER) {
+        return decode_varints(bytes, VARINT_HEADER.len());
+    }
+
+    if let Some(bytes) = input.trim_ascii_start().strip_prefix(COMMA_HEADER) {
+        let text = String::from_utf8_lossy(bytes);
+        if text.trim().is_empty() {
+            return Ok(vec![]);
+        }
+        return text.trim()
+            .split(',')
+            .enumerate()
+            .map(|(index, text)| {
+                let text = text.trim();
+                text.parse().map_err(|_| DiskMapError::InvalidSize { index, text: text.to_string() })
+            })
+            .collect();
+    }
+
+    input.iter()
+        .enumerate()
+        .filter(|(_, byte)| !byte.is_ascii_whitespace())
+        .map(|(offset, &byte)| match byte {
+            b'0'..=b'9' => Ok((byte - b'0') as usize),
+            _ => Err(DiskMapError::InvalidDigit { offset, byte }),
+        })
+        .collect()
+}
+
+fn decode_varints(bytes: &[u8], offset: usize) -> Result<Vec<usize>, DiskMapError> {
+    let mut sizes = vec![];
+    let mut value: usize = 0;
+    let mut shift = 0;
+    let mut start = offset;
+    for (index, &byte) in bytes.iter().enumerate() {
+        if shift == 0 {
+            start = offset + index;
+        }
+        let bits = (byte & 0x7f) as usize;
+        if shift >= usize::BITS || (bits << shift) >> shift != bits {
+            return Err(DiskMapError::VarintOverflow { offset: start });
+        }
+        value |= bits << shift;
+
+        if byte & 0x80 == 0 {
+            sizes.push(value);
+            value = 0;
+            shift = 0;
+        } else {
+            shift += 7;
+        }
+    }
+    if shift > 0 {
+        return Err(DiskMapError::TruncatedVarint { offset: start });
+    }
+
+    Ok(sizes)
+}
+
+pub(crate) fn read_disk_map(path: &str) -> Option<DiskMap> {
+    let input = fs::read(path).unwrap();
+    match parse_disk_map(&input).and_then(|sizes| DiskMap::from_unpacked(&unpack_sizes(&sizes))) {
+        Ok(disk) => Some(disk),
+        Err(err) => {
+            eprintln!("Invalid disk map: {}", err);
+            None
+        },
+    }
+}
 
 pub fn run() {
+    let Some(disk) = read_disk_map("./inputs/star_eighteen.txt") else {
+        return;
+    };
+    let checksum = checksum_files(&compact_whole_files(&disk, Fit::First));
+
+    println!("Result: {}", checksum);
+}
+
+pub fn run_strategies(specs: &str) {
+    let mut strategies = vec![];
+    for spec in specs.split(',') {
+        let Some(strategy) = parse_strategy(spec) else {
+            eprintln!("Unknown strategy {:?}, expected blocks, first, best, worst or fragments:<k>", spec);
+            return;
+        };
+        strategies.push(strategy);
+    }
+
+    let Some(disk) = read_disk_map("./inputs/star_eighteen.txt") else {
+        return;
+    };
+
+    println!("{:<14} {:>16} {:>10} {:>12} {:>8}", "strategy", "checksum", "fragments", "largest free", "moves");
+    for strategy in strategies {
+        let report = CompactionReport::new(&disk, &strategy.compact(&disk));
+        println!(
+            "{:<14} {:>16} {:>10} {:>12} {:>8}",
+            strategy.name(), report.checksum, report.fragments, report.largest_free, report.moves,
+        );
+    }
+}
+
+pub fn run_render(spec: &str, steps: bool) {
+    let Some(strategy) = parse_strategy(spec) else {
+        eprintln!("Unknown strategy {:?}, expected blocks, first, best, worst or fragments:<k>", spec);
+        return;
+    };
+    let Some(disk) = read_disk_map("./inputs/star_eighteen.txt") else {
+        return;
+    };
+    let files = strategy.compact(&disk);
+    let size = disk.size();
+
+    if size > MAX_RENDERED_BLOCKS {
+        println!("Disk has {} blocks, only disks up to {} blocks are rendered", size, MAX_RENDERED_BLOCKS);
+    } else {
+        let mut canvas = BlockCanvas::new(&disk.files, size);
+        println!("Before: {}", canvas.as_str());
+        if steps {
+            let moves = compaction_moves(&disk, &files);
+            if moves.len().saturating_mul(size) > MAX_TRACED_BLOCKS {
+                println!("{} moves on {} blocks, only traces up to {} blocks are printed", moves.len(), size, MAX_TRACED_BLOCKS);
+            } else {
+                let mut stdout = io::stdout().lock();
+                for mv in moves {
+                    canvas.apply(&mv);
+                    writeln!(stdout, "Move {} x{} {:>5} -> {:<5} {}", mv.id, mv.len, mv.from, mv.to, canvas.as_str()).unwrap();
+                }
+            }
+        }
+        println!("After:  {}", render_blocks(&files, size));
+    }
+    let sizes = layout_sizes(&files, disk.size());
+    println!("Disk map: {}", format_digits(&sizes).unwrap_or_else(|_| format_commas(&sizes)));
+}
+
+pub fn run_convert(format: &str) {
+    let input = fs::read("./inputs/star_eighteen.txt").unwrap();
+    let sizes = match parse_disk_map(&input) {
+        Ok(sizes) => sizes,
+        Err(err) => {
+            eprintln!("Invalid disk map: {}", err);
+            return;
+        },
+    };
+
+    let output = match format {
+        "digits" => match format_digits(&sizes) {
+            Ok(disk_map) => disk_map + "\n",
+            Err(err) => {
+                eprintln!("{}", err);
+                return;
+            },
+        },
+        "commas" => format_commas(&sizes) + "\n",
+        "varint" => {
+            io::stdout().write_all(&encode_varints(&sizes)).unwrap();
+            return;
+        },
+        _ => {
+            eprintln!("Unknown format {:?}, expected digits, commas or varint", format);
+            return;
+        },
+    };
+    print!("{}", output);
+}
+
+pub fn run_bench() {
     let file = fs::read_to_string("./inputs/star_eighteen.txt").unwrap();
     let unpacked_fs = unpack_filesystem(&file);
-    let defragged_fs = defrag_filesystem(&unpacked_fs);
-    let checksum = calculate_checksum(&defragged_fs);
 
-    println!("Result: {}", checksum);
+    let start = Instant::now();
+    let by_rescan = calculate_checksum(&defrag_filesystem(&unpacked_fs));
+    let rescan_time = start.elapsed();
+
+    let start = Instant::now();
+    let by_free_list = checksum_files(&compact_whole_files(&DiskMap::from_unpacked(&unpacked_fs).unwrap(), Fit::First));
+    let free_list_time = start.elapsed();
+
+    assert_eq!(by_rescan as u128, by_free_list);
+    println!("Rescan: {:?}", rescan_time);
+    println!("Free list: {:?}", free_list_time);
+    println!("Result: {}", by_free_list);
+}
+
+pub(crate) fn unpack_filesystem(filesystem: &String) -> Vec<(usize, Option<usize>)> {
+    unpack_sizes(&parse_disk_map(filesystem.as_bytes()).unwrap())
 }
 
-fn unpack_filesystem(filesystem: &String) -> Vec<(usize, Option<usize>)> {
+fn unpack_sizes(sizes: &[usize]) -> Vec<(usize, Option<usize>)> {
     let mut new_fs = vec![];
     let mut is_white_space = false;
     let mut index = 0;
-    for current_char in filesystem.chars() {
-        let size = current_char.to_string().parse::<usize>().unwrap();
+    for &size in sizes {
         let char_to_insert = match is_white_space {
             true => None,
             false => Some(index),
@@ -92,15 +613,181 @@ fn defrag_filesystem(unpacked_filesystem: &Vec<(usize, Option<usize>)>) -> Vec<O
     defragged_fs
 }
 
+// Free spans in disk order under a tree holding the longest span of each
+// subtree, so the leftmost span of at least a given length is found in
+// O(log n). Taking blocks from a span keeps it in its slot, shortened from
+// the front.
+struct FreeSpanIndex {
+    spans: Vec<FreeSpan>,
+    longest: Vec<usize>,
+    leaves: usize,
+}
+
+impl FreeSpanIndex {
+    fn new(free: &[FreeSpan]) -> FreeSpanIndex {
+        let leaves = free.len().next_power_of_two();
+        let mut longest = vec![0; 2 * leaves];
+        for (slot, span) in free.iter().enumerate() {
+            longest[leaves + slot] = span.len;
+        }
+        for node in (1..leaves).rev() {
+            longest[node] = longest[2 * node].max(longest[2 * node + 1]);
+        }
+
+        FreeSpanIndex { spans: free.to_vec(), longest, leaves }
+    }
+
+    // The leftmost span of at least `len` blocks, if it starts before `end`.
+    fn leftmost(&self, len: usize, end: usize) -> Option<usize> {
+        if len == 0 || self.longest[1] < len {
+            return None;
+        }
+        let mut node = 1;
+        while node < self.leaves {
+            node = if self.longest[2 * node] >= len { 2 * node } else { 2 * node + 1 };
+        }
+
+        let slot = node - self.leaves;
+        (self.spans[slot].start < end).then_some(slot)
+    }
+
+    // Takes `len` blocks from the front of a span and returns where they start.
+    fn take(&mut self, slot: usize, len: usize) -> usize {
+        let span = &mut self.spans[slot];
+        let start = span.start;
+        span.start += len;
+        span.len -= len;
+
+        let mut node = self.leaves + slot;
+        self.longest[node] = span.len;
+        while node > 1 {
+            node /= 2;
+            self.longest[node] = self.longest[2 * node].max(self.longest[2 * node + 1]);
+        }
+
+        start
+    }
+}
+
+// Moves every file, highest id first, into a free span that fits it and lies
+// to its left: the leftmost one, the shortest one or the longest one depending
+// on the fit. Space freed by a moved file is never reused: only files further
+// left are considered afterwards.
+pub(crate) fn compact_whole_files(disk: &DiskMap, fit: Fit) -> Vec<FileSpan> {
+    match fit {
+        Fit::First => compact_first_fit(disk),
+        Fit::Best | Fit::Worst => compact_by_length(disk, fit),
+    }
+}
+
+fn compact_first_fit(disk: &DiskMap) -> Vec<FileSpan> {
+    let mut free = FreeSpanIndex::new(&disk.free);
+    let mut files = disk.files.clone();
+    for file in files.iter_mut().rev() {
+        if let Some(slot) = free.leftmost(file.len, file.start) {
+            file.start = free.take(slot, file.len);
+        }
+    }
+
+    files
+}
+
+// Free spans are ordered by length and then start, so the shortest fitting
+// span is the first one from the file's length on and the longest is the
+// first one of the greatest length. Spans at or right of the file can never
+// be used again, since later files lie further left, so they are dropped as
+// they are found and each costs O(log n) once.
+fn compact_by_length(disk: &DiskMap, fit: Fit) -> Vec<FileSpan> {
+    let mut by_len: BTreeSet<(usize, usize)> = disk.free.iter()
+        .filter(|span| span.len > 0)
+        .map(|span| (span.len, span.start))
+        .collect();
+
+    let mut files = disk.files.clone();
+    for file in files.iter_mut().rev() {
+        let chosen = loop {
+            let candidate = match fit {
+                Fit::Worst => by_len.last().and_then(|&(len, _)| by_len.range((len, 0)..).next()),
+                _ => by_len.range((file.len, 0)..).next(),
+            };
+            match candidate.copied() {
+                Some((len, start)) if start >= file.start => by_len.remove(&(len, start)),
+                candidate => break candidate.filter(|&(len, _)| len >= file.len && file.len > 0),
+            };
+        };
+        let Some((len, start)) = chosen else {
+            continue;
+        };
+
+        by_len.remove(&(len, start));
+        if len > file.len {
+            by_len.insert((len - file.len, start + file.len));
+        }
+        file.start = start;
+    }
+
+    files
+}
+
+// Like block-by-block compaction, but each file ends up in at most
+// `max_fragments` spans. Free spans left of the file are filled in disk order
+// while more than one fragment is still allowed; the rest then goes whole into
+// the leftmost span that can hold it, or stays at the start of the file. One
+// fragment is first-fit whole-file compaction.
+pub(crate) fn compact_fragments(disk: &DiskMap, max_fragments: usize) -> Vec<FileSpan> {
+    let mut free = FreeSpanIndex::new(&disk.free);
+
+    let mut files = vec![];
+    for file in disk.files.iter().rev() {
+        let mut rest = file.len;
+        let mut pieces = 0;
+        while rest > 0 && pieces + 1 < max_fragments {
+            let Some(slot) = free.leftmost(1, file.start) else {
+                break;
+            };
+            let taken = free.spans[slot].len.min(rest);
+            files.push(FileSpan { id: file.id, start: free.take(slot, taken), len: taken });
+            rest -= taken;
+            pieces += 1;
+        }
+        if rest == 0 {
+            continue;
+        }
+
+        let start = match free.leftmost(rest, file.start) {
+            Some(slot) => free.take(slot, rest),
+            None => file.start,
+        };
+        files.push(FileSpan { id: file.id, start, len: rest });
+    }
+
+    files.reverse();
+    files
+}
+
+// Each span contributes id * (start + ... + start + len - 1). Disks can hold
+// billions of blocks, so the sum is taken in u128.
+pub(crate) fn checksum_files(files: &[FileSpan]) -> u128 {
+    files.iter()
+        .map(|file| {
+            let (id, start, len) = (file.id as u128, file.start as u128, file.len as u128);
+            id * (start * len + len * len.saturating_sub(1) / 2)
+        })
+        .sum()
+}
+
 fn calculate_checksum(unpacked_filesystem: &Vec<Option<usize>>) -> usize {
     unpacked_filesystem.iter()
-        .map(|c| c.unwrap_or(0))
         .enumerate()
-        .reduce(|acc, (index, val)| (index, acc.1 + (index * val)))
-        .unwrap().1
+        .map(|(index, id)| index * id.unwrap_or(0))
+        .sum()
 }
 
-
+// Random digit disk maps for the randomized compaction tests.
+#[cfg(test)]
+pub(crate) fn random_disk_map(rng: &mut Lcg, len: usize) -> String {
+    (0..len).map(|_| char::from_digit(rng.below(10) as u32, 10).unwrap()).collect()
+}
 
 #[cfg(test)]
 mod tests {
@@ -186,6 +873,308 @@ mod tests {
             1928,
         );
     }
-}
 
+    #[test]
+    fn test_compact_whole_files() {
+        let disk = DiskMap::from_unpacked(&unpack_filesystem(&"2333133121414131402".to_string())).unwrap();
+        let files = compact_whole_files(&disk, Fit::First);
+        assert_eq!(files[9], FileSpan { id: 9, start: 2, len: 2 });
+        assert_eq!(files[8], FileSpan { id: 8, start: 36, len: 4 });
+        assert_eq!(checksum_files(&files), 2858);
+    }
+
+    #[test]
+    fn test_checksum_files() {
+        assert_eq!(checksum_files(&[]), 0);
+        assert_eq!(checksum_files(&[FileSpan { id: 3, start: 4, len: 0 }]), 0);
+        assert_eq!(checksum_files(&[FileSpan { id: 3, start: 4, len: 3 }]), 3 * (4 + 5 + 6));
+
+        let (start, len) = (5_000_000_000u128, 4_000_000_000u128);
+        let files = [FileSpan { id: 1_000_000, start: start as usize, len: len as usize }];
+        assert_eq!(checksum_files(&files), 1_000_000 * (2 * start + len - 1) * len / 2);
+    }
+
+    #[test]
+    fn test_compact_whole_files_by_fit() {
+        let disk = DiskMap::from_unpacked(&unpack_filesystem(&"14121".to_string())).unwrap();
+        assert_eq!(checksum_files(&compact_whole_files(&disk, Fit::First)), 2 + 2);
+        assert_eq!(checksum_files(&compact_whole_files(&disk, Fit::Best)), 1 + 2 * 6);
+        assert_eq!(checksum_files(&compact_whole_files(&disk, Fit::Worst)), 2 + 2);
+
+        let disk = DiskMap::from_unpacked(&unpack_filesystem(&"12141".to_string())).unwrap();
+        assert_eq!(checksum_files(&compact_whole_files(&disk, Fit::First)), 2 + 2);
+        assert_eq!(checksum_files(&compact_whole_files(&disk, Fit::Best)), 2 + 2);
+        assert_eq!(checksum_files(&compact_whole_files(&disk, Fit::Worst)), 1 + 2 * 4);
+    }
+
+    #[test]
+    fn test_free_span_index() {
+        let mut free = FreeSpanIndex::new(&[
+            FreeSpan { start: 2, len: 3 },
+            FreeSpan { start: 8, len: 1 },
+            FreeSpan { start: 12, len: 6 },
+        ]);
+        assert_eq!(free.leftmost(1, 20), Some(0));
+        assert_eq!(free.leftmost(4, 20), Some(2));
+        assert_eq!(free.leftmost(4, 12), None);
+        assert_eq!(free.leftmost(7, 20), None);
+        assert_eq!(free.take(0, 3), 2);
+        assert_eq!(free.leftmost(1, 20), Some(1));
+        assert_eq!(free.take(2, 4), 12);
+        assert_eq!(free.leftmost(2, 20), Some(2));
+        assert_eq!(free.take(2, 2), 16);
+        assert_eq!(free.leftmost(2, 20), None);
+        assert_eq!(FreeSpanIndex::new(&[]).leftmost(1, 20), None);
+    }
+
+    #[test]
+    fn test_compact_whole_files_with_many_sizes() {
+        // Scans every free span for every file, as a reference.
+        let compact_by_scan = |disk: &DiskMap, fit: Fit| {
+            let mut free = disk.free.clone();
+            let mut files = disk.files.clone();
+            for file in files.iter_mut().rev() {
+                let candidates = free.iter().enumerate()
+                    .filter(|(_, span)| span.len >= file.len && span.start < file.start);
+                let chosen = match fit {
+                    Fit::First => candidates.min_by_key(|(_, span)| span.start),
+                    Fit::Best => candidates.min_by_key(|(_, span)| (span.len, span.start)),
+                    Fit::Worst => candidates.min_by_key(|(_, span)| (Reverse(span.len), span.start)),
+                };
+                if let Some((slot, _)) = chosen {
+                    file.start = free[slot].start;
+                    free[slot].start += file.len;
+                    free[slot].len -= file.len;
+                }
+            }
+            files
+        };
+
+        let mut rng = Lcg::new(45);
+        let sizes: Vec<usize> = (0..4000).map(|_| 1 + rng.below(1_000_000) as usize).collect();
+        let disk = DiskMap::from_unpacked(&unpack_sizes(&sizes)).unwrap();
+        for fit in [Fit::First, Fit::Best, Fit::Worst] {
+            assert_eq!(compact_whole_files(&disk, fit), compact_by_scan(&disk, fit), "{:?}", fit);
+        }
+        assert_eq!(compact_fragments(&disk, 1), compact_whole_files(&disk, Fit::First));
+        assert_eq!(
+            checksum_files(&compact_fragments(&disk, usize::MAX)),
+            checksum_files(&compact_blocks(&disk)),
+        );
+    }
+
+    #[test]
+    fn test_compaction_report() {
+        let disk = DiskMap::from_unpacked(&unpack_filesystem(&"2333133121414131402".to_string())).unwrap();
+        let reports: Vec<CompactionReport> = ["blocks", "first", "fragments:2"].iter()
+            .map(|spec| CompactionReport::new(&disk, &parse_strategy(spec).unwrap().compact(&disk)))
+            .collect();
+        assert_eq!(
+            reports,
+            [
+                CompactionReport { checksum: 1928, fragments: 13, largest_free: 14, moves: 7 },
+                CompactionReport { checksum: 2858, fragments: 10, largest_free: 5, moves: 4 },
+                CompactionReport { checksum: 1968, fragments: 12, largest_free: 12, moves: 6 },
+            ],
+        );
+        assert!(parse_strategy("fragments:x").is_none());
+        assert!(parse_strategy("fragments:0").is_none());
+        assert!(parse_strategy("next").is_none());
+    }
+
+    #[test]
+    fn test_compact_fragments_bounds() {
+        let mut rng = Lcg::new(7);
+        for len in 1..60 {
+            let disk_map = random_disk_map(&mut rng, len);
+            let disk = DiskMap::from_unpacked(&unpack_filesystem(&disk_map)).unwrap();
+            assert_eq!(compact_fragments(&disk, 1), compact_whole_files(&disk, Fit::First), "{}", disk_map);
+            assert_eq!(
+                checksum_files(&compact_fragments(&disk, usize::MAX)),
+                checksum_files(&compact_blocks(&disk)),
+                "{}",
+                disk_map,
+            );
+            for max_fragments in 2..4 {
+                let files = compact_fragments(&disk, max_fragments);
+                for file in &disk.files {
+                    assert!(files.iter().filter(|span| span.id == file.id).count() <= max_fragments);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_trace_compaction() {
+        let disk = DiskMap::from_unpacked(&unpack_filesystem(&"2333133121414131402".to_string())).unwrap();
+        let files = compact_whole_files(&disk, Fit::First);
+        let moves = compaction_moves(&disk, &files);
+        assert_eq!(
+            moves,
+            [
+                Move { id: 9, from: 40, to: 2, len: 2 },
+                Move { id: 7, from: 32, to: 8, len: 3 },
+                Move { id: 4, from: 19, to: 12, len: 2 },
+                Move { id: 2, from: 11, to: 4, len: 1 },
+            ],
+        );
+        let mut canvas = BlockCanvas::new(&disk.files, disk.size());
+        let mut trace = vec![canvas.as_str().to_string()];
+        for mv in &moves {
+            canvas.apply(mv);
+            trace.push(canvas.as_str().to_string());
+        }
+        assert_eq!(
+            trace,
+            [
+                "00...111...2...333.44.5555.6666.777.888899",
+                "0099.111...2...333.44.5555.6666.777.8888..",
+                "0099.1117772...333.44.5555.6666.....8888..",
+                "0099.111777244.333....5555.6666.....8888..",
+                "00992111777.44.333....5555.6666.....8888..",
+            ],
+        );
+        assert_eq!(render_blocks(&files, disk.size()), "00992111777.44.333....5555.6666.....8888..");
+
+        let files = compact_blocks(&disk);
+        let mut canvas = BlockCanvas::new(&disk.files, disk.size());
+        for mv in compaction_moves(&disk, &files) {
+            canvas.apply(&mv);
+        }
+        assert_eq!(canvas.as_str(), "0099811188827773336446555566..............");
+    }
+
+    #[test]
+    fn test_format_digits() {
+        let disk = DiskMap::from_unpacked(&unpack_filesystem(&"2333133121414131402".to_string())).unwrap();
+        assert_eq!(format_digits(&layout_sizes(&disk.files, disk.size())), Ok("2333133121414131402".to_string()));
+        assert_eq!(
+            format_digits(&layout_sizes(&compact_whole_files(&disk, Fit::First), disk.size())),
+            Ok("20201030312134414542".to_string()),
+        );
+        assert_eq!(
+            format_digits(&layout_sizes(&compact_blocks(&disk), disk.size())),
+            Ok("2020103030103030102010402905".to_string()),
+        );
+        assert_eq!(
+            format_digits(&layout_sizes(&[FileSpan { id: 0, start: 3, len: 12 }], 15)),
+            Err(SerializeError { start: 3, len: 12 }),
+        );
+        assert_eq!(format_digits(&layout_sizes(&[], 11)), Ok("0902".to_string()));
+    }
+
+    #[test]
+    fn test_parse_disk_map() {
+        assert_eq!(parse_disk_map(b"12345\n"), Ok(vec![1, 2, 3, 4, 5]));
+        assert_eq!(parse_disk_map(b" 12\r\n34 "), Ok(vec![1, 2, 3, 4]));
+        assert_eq!(parse_disk_map(b"12a4"), Err(DiskMapError::InvalidDigit { offset: 2, byte: b'a' }));
+        assert_eq!(parse_disk_map(b"commas:12, 0,345\n"), Ok(vec![12, 0, 345]));
+        assert_eq!(parse_disk_map(b"commas:12"), Ok(vec![12]));
+        assert_eq!(parse_disk_map(b"12"), Ok(vec![1, 2]));
+        assert_eq!(parse_disk_map(b"12,3"), Err(DiskMapError::InvalidDigit { offset: 2, byte: b',' }));
+        assert_eq!(
+            parse_disk_map(b"commas:12,,3"),
+            Err(DiskMapError::InvalidSize { index: 1, text: "".to_string() }),
+        );
+        assert_eq!(parse_disk_map(b"DMAP\x02\xac\x02\x00"), Ok(vec![2, 300, 0]));
+        assert_eq!(parse_disk_map(b"DMAP\x02\xac"), Err(DiskMapError::TruncatedVarint { offset: 5 }));
+        assert_eq!(
+            parse_disk_map(b"DMAP\xff\xff\xff\xff\xff\xff\xff\xff\xff\x7f"),
+            Err(DiskMapError::VarintOverflow { offset: 4 }),
+        );
+        assert_eq!(parse_disk_map(b""), Ok(vec![]));
+        assert_eq!(parse_disk_map(b"commas:\n"), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_disk_too_large() {
+        let sizes = parse_disk_map(format_commas(&[usize::MAX, 1, 1]).as_bytes()).unwrap();
+        assert_eq!(DiskMap::from_unpacked(&unpack_sizes(&sizes)), Err(DiskMapError::DiskTooLarge));
+        let sizes = parse_disk_map(&encode_varints(&[usize::MAX - 1, 1])).unwrap();
+        assert_eq!(DiskMap::from_unpacked(&unpack_sizes(&sizes)).unwrap().size(), usize::MAX);
+    }
+
+    #[test]
+    fn test_extended_formats_round_trip() {
+        let sizes = [3_000_000_000, 12, 1, 0, 127, 128, usize::MAX - 1, 0];
+        assert_eq!(parse_disk_map(&encode_varints(&sizes)), Ok(sizes.to_vec()));
+        assert_eq!(parse_disk_map(format_commas(&sizes).as_bytes()), Ok(sizes.to_vec()));
+        assert_eq!(
+            format_digits(&sizes),
+            Err(SerializeError { start: 0, len: 3_000_000_000 }),
+        );
+
+        let example = "2333133121414131402".to_string();
+        let by_digits = parse_disk_map(example.as_bytes()).unwrap();
+        assert_eq!(parse_disk_map(format_commas(&by_digits).as_bytes()), Ok(by_digits.clone()));
+        assert_eq!(parse_disk_map(&encode_varints(&by_digits)), Ok(by_digits.clone()));
+        assert_eq!(unpack_sizes(&by_digits), unpack_filesystem(&example));
+
+        // Layouts with files longer than a digit survive as comma-separated sizes.
+        let disk = DiskMap::from_unpacked(&unpack_sizes(&[10, 3, 4, 20, 12])).unwrap();
+        let compacted = compact_whole_files(&disk, Fit::Best);
+        let sizes = layout_sizes(&compacted, disk.size());
+        assert_eq!(sizes, [10, 3, 4, 0, 12, 20]);
+        let reparsed = DiskMap::from_unpacked(&unpack_sizes(&parse_disk_map(format_commas(&sizes).as_bytes()).unwrap())).unwrap();
+        assert_eq!(
+            reparsed.files,
+            [
+                FileSpan { id: 0, start: 0, len: 10 },
+                FileSpan { id: 1, start: 13, len: 4 },
+                FileSpan { id: 2, start: 17, len: 12 },
+            ],
+        );
+        assert_eq!(reparsed.size(), 49);
+    }
+
+    #[test]
+    fn test_disk_map_round_trip() {
+        let mut rng = Lcg::new(11);
+        let mut next_digit = |low: u64| char::from_digit((low + rng.below(10 - low)) as u32, 10).unwrap();
+        for len in 1..60 {
+            // Without empty files or an empty trailing gap the digits come back
+            // unchanged.
+            let disk_map: String = (0..len)
+                .map(|index| next_digit(if index % 2 == 0 || index == len - 1 { 1 } else { 0 }))
+                .collect();
+            let disk = DiskMap::from_unpacked(&unpack_filesystem(&disk_map)).unwrap();
+            assert_eq!(format_digits(&layout_sizes(&disk.files, disk.size())), Ok(disk_map.clone()));
+
+            // Otherwise the layout does.
+            let disk_map: String = (0..len).map(|_| next_digit(0)).collect();
+            let disk = DiskMap::from_unpacked(&unpack_filesystem(&disk_map)).unwrap();
+            let serialized = format_digits(&layout_sizes(&disk.files, disk.size())).unwrap();
+            let reparsed = DiskMap::from_unpacked(&unpack_filesystem(&serialized)).unwrap();
+            assert_eq!(reparsed.files, disk.files, "{} {}", disk_map, serialized);
+            assert_eq!(reparsed.size(), disk.size());
+
+            // Compacted layouts keep their shape with renumbered files.
+            let compacted = compact_fragments(&disk, 2);
+            let serialized = format_digits(&layout_sizes(&compacted, disk.size())).unwrap();
+            let reparsed = DiskMap::from_unpacked(&unpack_filesystem(&serialized)).unwrap();
+            assert_eq!(format_digits(&layout_sizes(&reparsed.files, reparsed.size())), Ok(serialized));
+            assert_eq!(
+                render_blocks(&reparsed.files, reparsed.size()).replace(|c: char| c.is_ascii_digit(), "#"),
+                render_blocks(&compacted, disk.size()).replace(|c: char| c.is_ascii_digit(), "#"),
+            );
+        }
+    }
 
+    #[test]
+    fn test_compact_whole_files_matches_rescan() {
+        let mut rng = Lcg::new(2024);
+        for len in 1..60 {
+            let disk_map = random_disk_map(&mut rng, len);
+            let unpacked_fs = unpack_filesystem(&disk_map);
+            if unpacked_fs.is_empty() {
+                continue;
+            }
+            assert_eq!(
+                checksum_files(&compact_whole_files(&DiskMap::from_unpacked(&unpacked_fs).unwrap(), Fit::First)),
+                calculate_checksum(&defrag_filesystem(&unpacked_fs)) as u128,
+                "{}",
+                disk_map,
+            );
+        }
+    }
+}
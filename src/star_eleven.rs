@@ -1,37 +1,84 @@
-use std::{collections::HashMap, fs};
+use std::{collections::{HashMap, HashSet}, fs, thread, time::Duration};
+
+const HIGHLIGHT: &str = "\x1b[1;33m";
+const RESET: &str = "\x1b[0m";
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
 
 pub fn run() {
     let file = fs::read_to_string("./inputs/star_eleven.txt").unwrap();
     let (guard, obstructions, w, h) = parse_input(&file);
-    let result = count_positions(&guard, &obstructions, w, h);
+    let index = ObstacleIndex::new(&obstructions, w, h);
+    let result = count_positions(&guard, &index);
     println!("Result: {}", result);
 }
 
-fn parse_input(input: &String) -> (Guard, Vec<Obstruction>, usize, usize) {
+pub fn run_path() {
+    let file = fs::read_to_string("./inputs/star_eleven.txt").unwrap();
+    let (guard, obstructions, w, h) = parse_input(&file);
+    let index = ObstacleIndex::new(&obstructions, w, h);
+    let mut canvas = PathCanvas::new(&index);
+    for state in patrol_states(&guard, &index) {
+        canvas.mark(&state);
+    }
+    println!("{}", canvas.render(None));
+}
+
+pub fn run_animation(delay_ms: u64, ansi: bool) {
+    let file = fs::read_to_string("./inputs/star_eleven.txt").unwrap();
+    let (guard, obstructions, w, h) = parse_input(&file);
+    let index = ObstacleIndex::new(&obstructions, w, h);
+    for frame in render_frames(&guard, &index, ansi) {
+        if ansi {
+            print!("{}{}", CLEAR_SCREEN, frame);
+        } else {
+            println!("{}\n", frame);
+        }
+        thread::sleep(Duration::from_millis(delay_ms));
+    }
+    println!();
+}
+
+pub fn run_multi(spec: &str) {
+    let Some(policy) = TurnPolicy::parse(spec) else {
+        eprintln!("Unknown turn policy {:?}, expected right, left, reverse or a table like #=right,O=reverse", spec);
+        return;
+    };
+    let file = fs::read_to_string("./inputs/star_eleven.txt").unwrap();
+    let (guards, index) = parse_patrol_input(&file);
+    let report = simulate_guards(&guards, &index, &policy);
+    println!("Guards: {}", guards.len());
+    println!("Covered by any guard: {}", report.union);
+    println!("Covered by every guard: {}", report.intersection);
+    match report.collision {
+        Some(collision) => println!(
+            "Guards {} and {} collide at ({}, {}) on tick {}",
+            collision.guards.0 + 1, collision.guards.1 + 1, collision.x, collision.y, collision.tick,
+        ),
+        None => println!("No collisions"),
+    }
+}
+
+pub(crate) fn parse_input(input: &str) -> (Guard, Vec<Obstruction>, usize, usize) {
 
     let w = input.lines().next().unwrap().len();
     let h = input.lines().count();
 
-    let mut input = input.clone();
-    input.retain(|s| !s.is_whitespace());
-
     let mut guard = Guard{x: 0, y: 0, direction: GuardDirection::Up};
     let mut obstructions = vec![];
 
-    for y in 0..h {
-        for x in 0..w {
-            let char = input.chars().nth(x + (y * w)).unwrap().to_string();
-            if char == "#" {
+    for (y, line) in input.lines().enumerate() {
+        for (x, char) in line.chars().enumerate() {
+            if char == '#' {
                 obstructions.push(
                     Obstruction{x, y}
                 );
             }
 
-            if "^v<>".contains(&char) {
+            if "^v<>".contains(char) {
                 guard.x = x;
                 guard.y = y;
 
-                guard.direction = match char.chars().next().unwrap() {
+                guard.direction = match char {
                     '^' => GuardDirection::Up,
                     'v' => GuardDirection::Down,
                     '<' => GuardDirection::Left,
@@ -45,46 +92,367 @@ fn parse_input(input: &String) -> (Guard, Vec<Obstruction>, usize, usize) {
     (guard, obstructions, w, h)
 }
 
-fn count_positions(guard: &Guard, obstructions: &Vec<Obstruction>, w: usize, h: usize) -> usize {
-    let mut guard = Guard{x: guard.x, y: guard.y, direction: guard.direction};
-    let mut visited_positions = HashMap::new();
-
-    while !guard.can_escape(w, h) {
-        visited_positions.insert((guard.x, guard.y), true);
+// Unlike `parse_input`, every marker becomes a guard and every character
+// other than `.` is an obstacle whose kind the turn policy can look up.
+fn parse_patrol_input(input: &str) -> (Vec<Guard>, ObstacleIndex) {
+    let w = input.lines().next().unwrap().len();
+    let h = input.lines().count();
 
-        if guard.is_obstructed(obstructions) {
-            guard.rotate_right();
-            continue;
+    let mut guards = vec![];
+    let mut index = ObstacleIndex::new(&[], w, h);
+
+    for (y, line) in input.lines().enumerate() {
+        for (x, char) in line.chars().enumerate() {
+            let direction = match char {
+                '.' => continue,
+                '^' => GuardDirection::Up,
+                'v' => GuardDirection::Down,
+                '<' => GuardDirection::Left,
+                '>' => GuardDirection::Right,
+                kind => {
+                    index.insert_with_kind(x, y, kind);
+                    continue;
+                },
+            };
+            guards.push(Guard{x, y, direction});
         }
+    }
 
-        guard.step();
+    (guards, index)
+}
 
-        visited_positions.insert((guard.x, guard.y), true);
+fn count_positions(guard: &Guard, index: &ObstacleIndex) -> usize {
+    let mut visited_positions = vec![false; index.w * index.h];
+    for state in patrol_states(guard, index) {
+        visited_positions[state.x + state.y * index.w] = true;
     }
 
-    visited_positions.keys().count()
+    visited_positions.iter().filter(|visited| **visited).count()
+}
+
+pub(crate) fn patrol_states(guard: &Guard, index: &ObstacleIndex) -> Vec<Guard> {
+    patrol_states_with_policy(guard, index, &TurnPolicy::Always(Turn::Right))
 }
 
+// Every state the guard goes through, one per step or turn, until it leaves
+// the map or hits the same obstacle from the same side twice.
+fn patrol_states_with_policy(guard: &Guard, index: &ObstacleIndex, policy: &TurnPolicy) -> Vec<Guard> {
+    let mut guard = *guard;
+    let mut states = vec![guard];
+    let mut obstacle_encounters = HashSet::new();
+
+    loop {
+        let mut walker = guard;
+        let obstacle = index.jump(&mut guard);
+        while (walker.x, walker.y) != (guard.x, guard.y) {
+            walker.step();
+            states.push(walker);
+        }
+
+        let obstacle = match obstacle {
+            Some(obstacle) if obstacle_encounters.insert(guard) => obstacle,
+            _ => break,
+        };
+        guard.turn(policy.turn_for(index.kind(obstacle.x, obstacle.y)));
+        states.push(guard);
+    }
+
+    states
+}
 
 #[derive(PartialEq, Debug)]
-struct Obstruction {
+struct Collision {
+    tick: usize,
     x: usize,
     y: usize,
+    guards: (usize, usize),
+}
+
+#[derive(PartialEq, Debug)]
+struct MultiPatrolReport {
+    union: usize,
+    intersection: usize,
+    collision: Option<Collision>,
+}
+
+// A guard's patrol ends in either an escape or a cycle, so its position on
+// any tick can be read off its state list. Each tick every guard takes one
+// step or turn, and two guards collide when they share a cell or swap cells.
+fn simulate_guards(guards: &[Guard], index: &ObstacleIndex, policy: &TurnPolicy) -> MultiPatrolReport {
+    let mut timelines = vec![];
+    let mut coverage = vec![0; index.w * index.h];
+    for guard in guards {
+        let mut states = patrol_states_with_policy(guard, index, policy);
+        let mut visited = vec![false; index.w * index.h];
+        for state in &states {
+            visited[state.x + state.y * index.w] = true;
+        }
+        for (cell, visited) in visited.iter().enumerate() {
+            if *visited {
+                coverage[cell] += 1;
+            }
+        }
+
+        let last = *states.last().unwrap();
+        let cycle_start = if last.can_escape(index.w, index.h) {
+            None
+        } else {
+            states.pop();
+            states.iter().position(|state| *state == last)
+        };
+        timelines.push(Timeline { states, cycle_start });
+    }
+
+    let mut collision: Option<Collision> = None;
+    for i in 0..timelines.len() {
+        for j in i + 1..timelines.len() {
+            let Some((tick, (x, y))) = first_collision(&timelines[i], &timelines[j]) else {
+                continue;
+            };
+            if collision.as_ref().is_none_or(|collision| tick < collision.tick) {
+                collision = Some(Collision { tick, x, y, guards: (i, j) });
+            }
+        }
+    }
+
+    MultiPatrolReport {
+        union: coverage.iter().filter(|count| **count > 0).count(),
+        intersection: coverage.iter().filter(|count| **count == guards.len()).count(),
+        collision,
+    }
+}
+
+type Cell = (usize, usize);
+
+struct Timeline {
+    states: Vec<Guard>,
+    cycle_start: Option<usize>,
+}
+
+impl Timeline {
+    fn position_at(&self, tick: usize) -> Option<Cell> {
+        match self.cycle_start {
+            _ if tick < self.states.len() => Some(self.states[tick]),
+            Some(start) => Some(self.states[start + (tick - start) % (self.states.len() - start)]),
+            None => None,
+        }
+        .map(|state| (state.x, state.y))
+    }
+}
+
+// Steps both guards until each has left the map or entered its cycle. From
+// then on a guard is at cycle phase k exactly on the ticks congruent to
+// start + k modulo the cycle length, so every shared cell or swapped pair of
+// cells gives a pair of congruences whose smallest solution is the first tick
+// the guards meet there.
+fn first_collision(a: &Timeline, b: &Timeline) -> Option<(usize, Cell)> {
+    let horizon = a.states.len().max(b.states.len());
+    for tick in 0..=horizon {
+        let (Some(position_a), Some(position_b)) = (a.position_at(tick), b.position_at(tick)) else {
+            continue;
+        };
+        let swapped = tick > 0
+            && a.position_at(tick - 1) == Some(position_b)
+            && b.position_at(tick - 1) == Some(position_a);
+        if position_a == position_b || swapped {
+            return Some((tick, position_a));
+        }
+    }
+
+    let (Some(start_a), Some(start_b)) = (a.cycle_start, b.cycle_start) else {
+        return None;
+    };
+    let cycle_a: Vec<Cell> = a.states[start_a..].iter().map(|state| (state.x, state.y)).collect();
+    let cycle_b: Vec<Cell> = b.states[start_b..].iter().map(|state| (state.x, state.y)).collect();
+
+    let mut phases_b: HashMap<Cell, Vec<usize>> = HashMap::new();
+    let mut steps_b: HashMap<(Cell, Cell), Vec<usize>> = HashMap::new();
+    for (phase, &cell) in cycle_b.iter().enumerate() {
+        phases_b.entry(cell).or_default().push(phase);
+        let previous = cycle_b[(phase + cycle_b.len() - 1) % cycle_b.len()];
+        if previous != cell {
+            steps_b.entry((previous, cell)).or_default().push(phase);
+        }
+    }
+
+    let mut first: Option<(usize, Cell)> = None;
+    for (phase_a, &cell) in cycle_a.iter().enumerate() {
+        let previous = cycle_a[(phase_a + cycle_a.len() - 1) % cycle_a.len()];
+        let same_cell = phases_b.get(&cell).into_iter().flatten();
+        let swapped = steps_b.get(&(cell, previous)).into_iter().flatten();
+        for &phase_b in same_cell.chain(swapped) {
+            let tick = solve_congruences(
+                start_a + phase_a, cycle_a.len(),
+                start_b + phase_b, cycle_b.len(),
+                horizon + 1,
+            );
+            if let Some(tick) = tick.filter(|tick| first.is_none_or(|(first, _)| *tick < first)) {
+                first = Some((tick, cell));
+            }
+        }
+    }
+
+    first
+}
+
+// The smallest tick from `from` onwards that is congruent to `a` modulo `m`
+// and to `b` modulo `n`, if there is one.
+fn solve_congruences(a: usize, m: usize, b: usize, n: usize, from: usize) -> Option<usize> {
+    let (m, n) = (m as i128, n as i128);
+    let (g, p, _) = extended_gcd(m, n);
+    let difference = b as i128 - a as i128;
+    if difference % g != 0 {
+        return None;
+    }
+
+    let lcm = m / g * n;
+    let t = (a as i128 + m * (difference / g * p).rem_euclid(n / g)).rem_euclid(lcm);
+    let from = from as i128;
+    let t = if t >= from { t } else { t + (from - t + lcm - 1) / lcm * lcm };
+    usize::try_from(t).ok()
+}
+
+// Returns (g, p, q) with p * a + q * b = g = gcd(a, b).
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, p, q) = extended_gcd(b, a % b);
+        (g, q, p - a / b * q)
+    }
+}
+
+// Frames are drawn one at a time on a single canvas as the caller asks for
+// them, so only the current frame is ever held in memory.
+fn render_frames(guard: &Guard, index: &ObstacleIndex, ansi: bool) -> impl Iterator<Item = String> {
+    let mut canvas = PathCanvas::new(index);
+    patrol_states(guard, index).into_iter().map(move |state| {
+        canvas.mark(&state);
+        canvas.render(if ansi { Some(&state) } else { None })
+    })
+}
+
+// A character buffer of the map that the guard's path is drawn onto, so
+// each frame is a single pass over the cells.
+struct PathCanvas {
+    w: usize,
+    cells: Vec<char>,
+}
+
+impl PathCanvas {
+    pub fn new(index: &ObstacleIndex) -> Self {
+        let mut cells = vec!['.'; index.w * index.h];
+        for y in 0..index.h {
+            for x in 0..index.w {
+                if index.contains(x, y) {
+                    cells[x + y * index.w] = index.kind(x, y);
+                }
+            }
+        }
+        Self { w: index.w, cells }
+    }
+
+    pub fn mark(&mut self, guard: &Guard) {
+        self.cells[guard.x + guard.y * self.w] = guard.direction.glyph();
+    }
+
+    pub fn render(&self, highlighted: Option<&Guard>) -> String {
+        let mut lines = vec![];
+        for (y, row) in self.cells.chunks(self.w).enumerate() {
+            let mut line = String::new();
+            for (x, char) in row.iter().enumerate() {
+                match highlighted {
+                    Some(guard) if (guard.x, guard.y) == (x, y) => {
+                        line.push_str(&format!("{}{}{}", HIGHLIGHT, char, RESET));
+                    },
+                    _ => line.push(*char),
+                }
+            }
+            lines.push(line);
+        }
+        lines.join("\n")
+    }
 }
 
+
 #[derive(PartialEq, Debug, Copy, Clone)]
-enum GuardDirection {
+pub(crate) struct Obstruction {
+    pub x: usize,
+    pub y: usize,
+}
+
+#[derive(PartialEq, Debug, Copy, Clone, Eq, Hash)]
+pub(crate) enum GuardDirection {
     Up,
     Down,
     Left,
     Right,
 }
 
-#[derive(PartialEq, Debug)]
-struct Guard {
-    x: usize,
-    y: usize,
-    direction: GuardDirection,
+impl GuardDirection {
+    pub fn glyph(&self) -> char {
+        match self {
+            GuardDirection::Up => '^',
+            GuardDirection::Down => 'v',
+            GuardDirection::Left => '<',
+            GuardDirection::Right => '>',
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub(crate) enum Turn {
+    Right,
+    Left,
+    Reverse,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub(crate) enum TurnPolicy {
+    Always(Turn),
+    // Obstacle kinds missing from the table make the guard turn right.
+    ByObstacle(HashMap<char, Turn>),
+}
+
+impl TurnPolicy {
+    // Accepts `right`, `left`, `reverse` or a table like `#=right,O=reverse`.
+    pub fn parse(input: &str) -> Option<Self> {
+        let parse_turn = |turn: &str| match turn {
+            "right" => Some(Turn::Right),
+            "left" => Some(Turn::Left),
+            "reverse" => Some(Turn::Reverse),
+            _ => None,
+        };
+
+        if !input.contains('=') {
+            return parse_turn(input).map(TurnPolicy::Always);
+        }
+
+        let mut table = HashMap::new();
+        for rule in input.split(',') {
+            let (kind, turn) = rule.split_once('=')?;
+            let mut kind = kind.chars();
+            match (kind.next(), kind.next()) {
+                (Some(kind), None) => table.insert(kind, parse_turn(turn)?),
+                _ => return None,
+            };
+        }
+        Some(TurnPolicy::ByObstacle(table))
+    }
+
+    pub fn turn_for(&self, kind: char) -> Turn {
+        match self {
+            TurnPolicy::Always(turn) => *turn,
+            TurnPolicy::ByObstacle(table) => table.get(&kind).copied().unwrap_or(Turn::Right),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Copy, Clone, Eq, Hash)]
+pub(crate) struct Guard {
+    pub x: usize,
+    pub y: usize,
+    pub direction: GuardDirection,
 }
 
 impl Guard {
@@ -113,6 +481,26 @@ impl Guard {
         }
     }
 
+    pub fn rotate_left(&mut self) {
+        self.direction = match self.direction {
+            GuardDirection::Up => GuardDirection::Left,
+            GuardDirection::Left => GuardDirection::Down,
+            GuardDirection::Down => GuardDirection::Right,
+            GuardDirection::Right => GuardDirection::Up,
+        }
+    }
+
+    pub fn turn(&mut self, turn: Turn) {
+        match turn {
+            Turn::Right => self.rotate_right(),
+            Turn::Left => self.rotate_left(),
+            Turn::Reverse => {
+                self.rotate_right();
+                self.rotate_right();
+            },
+        }
+    }
+
     pub fn step(&mut self) {
         match self.direction {
             GuardDirection::Up => {
@@ -131,17 +519,103 @@ impl Guard {
             },
         };
     }
+}
 
-    pub fn is_obstructed(&self, obstructions: &Vec<Obstruction>) -> bool {
-        let mut virtual_guard = Guard {x: self.x, y: self.y, direction: self.direction};
-        virtual_guard.step();
+// Obstacles sorted by position within every row and column, so the guard
+// can jump straight to the next one in a binary search.
+#[derive(Debug, Clone)]
+pub(crate) struct ObstacleIndex {
+    pub w: usize,
+    pub h: usize,
+    rows: Vec<Vec<usize>>,
+    columns: Vec<Vec<usize>>,
+    kinds: HashMap<(usize, usize), char>,
+}
+
+impl ObstacleIndex {
+    pub fn new(obstructions: &[Obstruction], w: usize, h: usize) -> Self {
+        let mut index = Self {
+            w,
+            h,
+            rows: vec![vec![]; h],
+            columns: vec![vec![]; w],
+            kinds: HashMap::new(),
+        };
         for obstruction in obstructions {
-            if obstruction.x == virtual_guard.x && obstruction.y == virtual_guard.y {
-                return true;
-            }
+            index.insert(obstruction.x, obstruction.y);
         }
+        index
+    }
 
-        false
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        self.rows[y].binary_search(&x).is_ok()
+    }
+
+    pub fn insert(&mut self, x: usize, y: usize) {
+        if let Err(i) = self.rows[y].binary_search(&x) {
+            self.rows[y].insert(i, x);
+        }
+        if let Err(i) = self.columns[x].binary_search(&y) {
+            self.columns[x].insert(i, y);
+        }
+    }
+
+    // Obstacles without a recorded kind are plain `#` ones.
+    pub fn insert_with_kind(&mut self, x: usize, y: usize, kind: char) {
+        self.insert(x, y);
+        if kind != '#' {
+            self.kinds.insert((x, y), kind);
+        }
+    }
+
+    pub fn kind(&self, x: usize, y: usize) -> char {
+        self.kinds.get(&(x, y)).copied().unwrap_or('#')
+    }
+
+    pub fn remove(&mut self, x: usize, y: usize) {
+        self.kinds.remove(&(x, y));
+        if let Ok(i) = self.rows[y].binary_search(&x) {
+            self.rows[y].remove(i);
+        }
+        if let Ok(i) = self.columns[x].binary_search(&y) {
+            self.columns[x].remove(i);
+        }
+    }
+
+    // Moves the guard up to the next obstacle in its way and returns it, or
+    // to the edge of the map if there is nothing left to stop it.
+    pub fn jump(&self, guard: &mut Guard) -> Option<Obstruction> {
+        if guard.can_escape(self.w, self.h) {
+            return None;
+        }
+
+        let row = &self.rows[guard.y];
+        let column = &self.columns[guard.x];
+        let obstacle = match guard.direction {
+            GuardDirection::Up => column[..column.partition_point(|y| *y < guard.y)]
+                .last()
+                .map(|y| Obstruction{x: guard.x, y: *y}),
+            GuardDirection::Down => column.get(column.partition_point(|y| *y <= guard.y))
+                .map(|y| Obstruction{x: guard.x, y: *y}),
+            GuardDirection::Left => row[..row.partition_point(|x| *x < guard.x)]
+                .last()
+                .map(|x| Obstruction{x: *x, y: guard.y}),
+            GuardDirection::Right => row.get(row.partition_point(|x| *x <= guard.x))
+                .map(|x| Obstruction{x: *x, y: guard.y}),
+        };
+
+        match (guard.direction, obstacle) {
+            (GuardDirection::Up, Some(obstacle)) => guard.y = obstacle.y + 1,
+            (GuardDirection::Down, Some(obstacle)) => guard.y = obstacle.y - 1,
+            (GuardDirection::Left, Some(obstacle)) => guard.x = obstacle.x + 1,
+            (GuardDirection::Right, Some(obstacle)) => guard.x = obstacle.x - 1,
+            (GuardDirection::Up, None) => guard.y = 0,
+            (GuardDirection::Down, None) => guard.y = self.h - 1,
+            (GuardDirection::Left, None) => guard.x = 0,
+            (GuardDirection::Right, None) => guard.x = self.w - 1,
+        }
+
+        obstacle
     }
 }
 
@@ -149,6 +623,7 @@ impl Guard {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::star_ten::Lcg;
 
     #[test]
     fn test_parse_input() {
@@ -197,8 +672,218 @@ mod tests {
             "#.........",
             "......#...",
         ].join("\n");
-        let (guard, obstructions, _, _) = parse_input(&input);
-        assert_eq!(count_positions(&guard, &obstructions, 10, 10), 41);
+        let (guard, obstructions, w, h) = parse_input(&input);
+        assert_eq!(count_positions(&guard, &ObstacleIndex::new(&obstructions, w, h)), 41);
+    }
+
+    #[test]
+    fn test_patrol_states() {
+        let index = ObstacleIndex::new(&[Obstruction{x: 1, y: 0}], 3, 3);
+        let guard = Guard{x: 1, y: 2, direction: GuardDirection::Up};
+        assert_eq!(
+            patrol_states(&guard, &index),
+            vec![
+                Guard{x: 1, y: 2, direction: GuardDirection::Up},
+                Guard{x: 1, y: 1, direction: GuardDirection::Up},
+                Guard{x: 1, y: 1, direction: GuardDirection::Right},
+                Guard{x: 2, y: 1, direction: GuardDirection::Right},
+            ],
+        );
+
+        let index = ObstacleIndex::new(&[
+            Obstruction{x: 1, y: 0},
+            Obstruction{x: 3, y: 1},
+            Obstruction{x: 0, y: 2},
+            Obstruction{x: 2, y: 3},
+        ], 4, 4);
+        let guard = Guard{x: 1, y: 2, direction: GuardDirection::Up};
+        assert_eq!(patrol_states(&guard, &index).len(), 10);
+    }
+
+    #[test]
+    fn test_render_frames() {
+        let index = ObstacleIndex::new(&[Obstruction{x: 1, y: 0}], 3, 3);
+        let guard = Guard{x: 1, y: 2, direction: GuardDirection::Up};
+        assert_eq!(
+            render_frames(&guard, &index, false).collect::<Vec<String>>(),
+            vec![
+                [".#.", "...", ".^."].join("\n"),
+                [".#.", ".^.", ".^."].join("\n"),
+                [".#.", ".>.", ".^."].join("\n"),
+                [".#.", ".>>", ".^."].join("\n"),
+            ],
+        );
+        assert_eq!(
+            render_frames(&guard, &index, true).nth(1).unwrap(),
+            [".#.", ".\x1b[1;33m^\x1b[0m.", ".^."].join("\n"),
+        );
+    }
+
+    #[test]
+    fn test_parse_patrol_input() {
+        let (guards, index) = parse_patrol_input(&["O.^", "#v."].join("\n"));
+        assert_eq!(
+            guards,
+            vec![
+                Guard{x: 2, y: 0, direction: GuardDirection::Up},
+                Guard{x: 1, y: 1, direction: GuardDirection::Down},
+            ],
+        );
+        assert!(index.contains(0, 0));
+        assert!(index.contains(0, 1));
+        assert!(!index.contains(1, 0));
+        assert_eq!(index.kind(0, 0), 'O');
+        assert_eq!(index.kind(0, 1), '#');
+    }
+
+    #[test]
+    fn test_turn_policy_parse() {
+        assert_eq!(TurnPolicy::parse("right"), Some(TurnPolicy::Always(Turn::Right)));
+        assert_eq!(TurnPolicy::parse("left"), Some(TurnPolicy::Always(Turn::Left)));
+        assert_eq!(TurnPolicy::parse("reverse"), Some(TurnPolicy::Always(Turn::Reverse)));
+        assert_eq!(
+            TurnPolicy::parse("#=left,O=reverse"),
+            Some(TurnPolicy::ByObstacle(HashMap::from([('#', Turn::Left), ('O', Turn::Reverse)]))),
+        );
+        assert_eq!(TurnPolicy::parse("up"), None);
+        assert_eq!(TurnPolicy::parse("#=up"), None);
+        assert_eq!(TurnPolicy::parse("ab=left"), None);
+
+        let policy = TurnPolicy::parse("O=reverse").unwrap();
+        assert_eq!(policy.turn_for('O'), Turn::Reverse);
+        assert_eq!(policy.turn_for('#'), Turn::Right);
+    }
+
+    #[test]
+    fn test_patrol_states_with_policy() {
+        let index = ObstacleIndex::new(&[Obstruction{x: 1, y: 0}], 3, 3);
+        let guard = Guard{x: 1, y: 2, direction: GuardDirection::Up};
+        assert_eq!(
+            patrol_states_with_policy(&guard, &index, &TurnPolicy::Always(Turn::Left)),
+            vec![
+                Guard{x: 1, y: 2, direction: GuardDirection::Up},
+                Guard{x: 1, y: 1, direction: GuardDirection::Up},
+                Guard{x: 1, y: 1, direction: GuardDirection::Left},
+                Guard{x: 0, y: 1, direction: GuardDirection::Left},
+            ],
+        );
+
+        let (guards, index) = parse_patrol_input("#.>.O");
+        let states = patrol_states_with_policy(&guards[0], &index, &TurnPolicy::parse("O=reverse,#=reverse").unwrap());
+        assert_eq!(
+            states.iter().map(|state| (state.x, state.direction)).collect::<Vec<_>>(),
+            vec![
+                (2, GuardDirection::Right),
+                (3, GuardDirection::Right),
+                (3, GuardDirection::Left),
+                (2, GuardDirection::Left),
+                (1, GuardDirection::Left),
+                (1, GuardDirection::Right),
+                (2, GuardDirection::Right),
+                (3, GuardDirection::Right),
+            ],
+        );
+
+        let states = patrol_states_with_policy(&guards[0], &index, &TurnPolicy::parse("O=reverse").unwrap());
+        assert_eq!(states.last(), Some(&Guard{x: 1, y: 0, direction: GuardDirection::Up}));
+    }
+
+    #[test]
+    fn test_simulate_guards() {
+        let policy = TurnPolicy::Always(Turn::Right);
+        let (guards, index) = parse_patrol_input("#>...<#");
+        assert_eq!(
+            simulate_guards(&guards, &index, &policy),
+            MultiPatrolReport {
+                union: 5,
+                intersection: 5,
+                collision: Some(Collision { tick: 2, x: 3, y: 0, guards: (0, 1) }),
+            },
+        );
+
+        let (guards, index) = parse_patrol_input("#>..<#");
+        assert_eq!(
+            simulate_guards(&guards, &index, &policy).collision,
+            Some(Collision { tick: 2, x: 3, y: 0, guards: (0, 1) }),
+        );
+
+        let (guards, index) = parse_patrol_input(&[">...", "....", "<..."].join("\n"));
+        assert_eq!(
+            simulate_guards(&guards, &index, &policy),
+            MultiPatrolReport { union: 5, intersection: 0, collision: None },
+        );
+
+        let (guards, index) = parse_patrol_input(&["#>.#", "#.<#"].join("\n"));
+        let report = simulate_guards(&guards, &index, &TurnPolicy::Always(Turn::Reverse));
+        assert_eq!(report, MultiPatrolReport { union: 4, intersection: 0, collision: None });
+    }
+
+    #[test]
+    fn test_simulate_guards_with_coprime_cycles() {
+        // Each guard circles its own 3-wide box, with cycle lengths of twice
+        // 7, 11, ..., 31, so the joint period is far too long to step through.
+        let primes = [7, 11, 13, 17, 19, 23, 29, 31];
+        let h = 31;
+        let mut rows = vec![String::new(); h];
+        for prime in primes {
+            let box_h = prime - 3 + 2;
+            for (y, row) in rows.iter_mut().enumerate() {
+                row.push_str(match y {
+                    _ if y >= box_h => ".....",
+                    0 => "#####",
+                    1 => "#>..#",
+                    _ if y == box_h - 1 => "#####",
+                    _ => "#...#",
+                });
+            }
+        }
+        let (guards, index) = parse_patrol_input(&rows.join("\n"));
+        let report = simulate_guards(&guards, &index, &TurnPolicy::Always(Turn::Right));
+        assert_eq!(report.collision, None);
+        assert_eq!(report.union, primes.iter().map(|prime| 2 * prime - 4).sum::<usize>());
+    }
+
+    #[test]
+    fn test_first_collision_matches_stepping() {
+        let mut rng = Lcg::new(35);
+        let mut next = |bound: u64| rng.below(bound) as usize;
+        // Timelines are made up rather than walked, so guards jump between
+        // arbitrary cells and their cycles can line up long after they start.
+        for _ in 0..1000 {
+            let mut timeline = || {
+                let len = 1 + next(20);
+                let states: Vec<Guard> = (0..len)
+                    .map(|_| Guard{x: next(4), y: next(4), direction: GuardDirection::Up})
+                    .collect();
+                let cycle_start = if next(5) == 0 { None } else { Some(next(len as u64)) };
+                Timeline { states, cycle_start }
+            };
+            let (a, b) = (timeline(), timeline());
+
+            let cycle = |timeline: &Timeline| timeline.cycle_start.map_or(1, |start| timeline.states.len() - start);
+            let (cycle_a, cycle_b) = (cycle(&a), cycle(&b));
+            let gcd = extended_gcd(cycle_a as i128, cycle_b as i128).0 as usize;
+            let ticks = a.states.len().max(b.states.len()) + cycle_a / gcd * cycle_b;
+            let stepped = (0..=ticks).find_map(|tick| {
+                let (position_a, position_b) = (a.position_at(tick)?, b.position_at(tick)?);
+                let swapped = tick > 0
+                    && a.position_at(tick - 1) == Some(position_b)
+                    && b.position_at(tick - 1) == Some(position_a);
+                (position_a == position_b || swapped).then_some((tick, position_a))
+            });
+            assert_eq!(first_collision(&a, &b), stepped);
+        }
+    }
+
+    #[test]
+    fn test_guard_turn() {
+        let mut guard = Guard{x: 0, y: 0, direction: GuardDirection::Up};
+        guard.turn(Turn::Left);
+        assert_eq!(guard.direction, GuardDirection::Left);
+        guard.turn(Turn::Reverse);
+        assert_eq!(guard.direction, GuardDirection::Right);
+        guard.turn(Turn::Right);
+        assert_eq!(guard.direction, GuardDirection::Down);
     }
 
     #[test]
@@ -258,53 +943,70 @@ mod tests {
     }
 
     #[test]
-    fn test_is_guard_obstructed() {
-        let obstructions = vec![
-            Obstruction{x: 2, y: 1}
-        ];
-        let guard = Guard{x: 2, y: 2, direction: GuardDirection::Up};
-        assert!(guard.is_obstructed(&obstructions));
+    fn test_obstacle_index_jump() {
+        let index = ObstacleIndex::new(&[Obstruction{x: 2, y: 0}, Obstruction{x: 2, y: 1}], 5, 5);
+        let mut guard = Guard{x: 2, y: 4, direction: GuardDirection::Up};
+        assert_eq!(index.jump(&mut guard), Some(Obstruction{x: 2, y: 1}));
+        assert_eq!(guard, Guard{x: 2, y: 2, direction: GuardDirection::Up});
+
+        let index = ObstacleIndex::new(&[Obstruction{x: 3, y: 2}, Obstruction{x: 4, y: 2}], 5, 5);
+        let mut guard = Guard{x: 0, y: 2, direction: GuardDirection::Right};
+        assert_eq!(index.jump(&mut guard), Some(Obstruction{x: 3, y: 2}));
+        assert_eq!(guard, Guard{x: 2, y: 2, direction: GuardDirection::Right});
+
+        let index = ObstacleIndex::new(&[Obstruction{x: 2, y: 3}], 5, 5);
+        let mut guard = Guard{x: 2, y: 2, direction: GuardDirection::Down};
+        assert_eq!(index.jump(&mut guard), Some(Obstruction{x: 2, y: 3}));
+        assert_eq!(guard, Guard{x: 2, y: 2, direction: GuardDirection::Down});
 
-        let obstructions = vec![
-            Obstruction{x: 3, y: 2}
-        ];
-        let guard = Guard{x: 2, y: 2, direction: GuardDirection::Right};
-        assert!(guard.is_obstructed(&obstructions));
+        let index = ObstacleIndex::new(&[Obstruction{x: 0, y: 2}, Obstruction{x: 4, y: 2}], 5, 5);
+        let mut guard = Guard{x: 3, y: 2, direction: GuardDirection::Left};
+        assert_eq!(index.jump(&mut guard), Some(Obstruction{x: 0, y: 2}));
+        assert_eq!(guard, Guard{x: 1, y: 2, direction: GuardDirection::Left});
 
-        let obstructions = vec![
-            Obstruction{x: 2, y: 3}
-        ];
-        let guard = Guard{x: 2, y: 2, direction: GuardDirection::Down};
-        assert!(guard.is_obstructed(&obstructions));
+        let index = ObstacleIndex::new(&[Obstruction{x: 2, y: 2}], 5, 5);
+        let mut guard = Guard{x: 4, y: 4, direction: GuardDirection::Up};
+        assert_eq!(index.jump(&mut guard), None);
+        assert_eq!(guard, Guard{x: 4, y: 0, direction: GuardDirection::Up});
 
-        let obstructions = vec![
-            Obstruction{x: 1, y: 2}
-        ];
-        let guard = Guard{x: 2, y: 2, direction: GuardDirection::Left};
-        assert!(guard.is_obstructed(&obstructions));
+        let mut guard = Guard{x: 1, y: 2, direction: GuardDirection::Left};
+        assert_eq!(index.jump(&mut guard), None);
+        assert_eq!(guard, Guard{x: 0, y: 2, direction: GuardDirection::Left});
 
-        let obstructions = vec![
-            Obstruction{x: 2, y: 2}
-        ];
-        let guard = Guard{x: 4, y: 4, direction: GuardDirection::Up};
-        assert!(!guard.is_obstructed(&obstructions));
+        let mut guard = Guard{x: 3, y: 2, direction: GuardDirection::Right};
+        assert_eq!(index.jump(&mut guard), None);
+        assert_eq!(guard, Guard{x: 4, y: 2, direction: GuardDirection::Right});
 
-        let obstructions = vec![
-            Obstruction{x: 2, y: 2}
-        ];
-        let guard = Guard{x: 4, y: 4, direction: GuardDirection::Right};
-        assert!(!guard.is_obstructed(&obstructions));
+        let mut guard = Guard{x: 2, y: 3, direction: GuardDirection::Down};
+        assert_eq!(index.jump(&mut guard), None);
+        assert_eq!(guard, Guard{x: 2, y: 4, direction: GuardDirection::Down});
+    }
 
-        let obstructions = vec![
-            Obstruction{x: 2, y: 2}
-        ];
-        let guard = Guard{x: 4, y: 4, direction: GuardDirection::Down};
-        assert!(!guard.is_obstructed(&obstructions));
+    #[test]
+    fn test_obstacle_index_insert_remove() {
+        let mut index = ObstacleIndex::new(&[Obstruction{x: 1, y: 1}], 3, 3);
+        assert!(index.contains(1, 1));
+        assert!(!index.contains(2, 1));
+        index.insert(2, 1);
+        assert!(index.contains(2, 1));
+        let mut guard = Guard{x: 1, y: 0, direction: GuardDirection::Down};
+        assert_eq!(index.jump(&mut guard), Some(Obstruction{x: 1, y: 1}));
+        index.remove(1, 1);
+        assert!(!index.contains(1, 1));
+        let mut guard = Guard{x: 1, y: 0, direction: GuardDirection::Down};
+        assert_eq!(index.jump(&mut guard), None);
+        assert_eq!(guard, Guard{x: 1, y: 2, direction: GuardDirection::Down});
+    }
 
+    #[test]
+    fn test_count_positions_large_map() {
+        let size = 3_000;
         let obstructions = vec![
-            Obstruction{x: 2, y: 2}
+            Obstruction{x: 10, y: 0},
+            Obstruction{x: size - 1, y: 1},
         ];
-        let guard = Guard{x: 4, y: 4, direction: GuardDirection::Left};
-        assert!(!guard.is_obstructed(&obstructions));
+        let index = ObstacleIndex::new(&obstructions, size, size);
+        let guard = Guard{x: 10, y: size - 1, direction: GuardDirection::Up};
+        assert_eq!(count_positions(&guard, &index), (size - 1) + (size - 12) + (size - 2));
     }
 }
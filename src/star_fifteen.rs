@@ -1,6 +1,11 @@
-use std::{collections::HashMap, fs};
+use std::{collections::{BTreeMap, HashMap, HashSet}, fs};
 use regex::Regex;
 
+const PALETTE: [&str; 6] = ["\x1b[31m", "\x1b[32m", "\x1b[33m", "\x1b[34m", "\x1b[35m", "\x1b[36m"];
+const BOLD: &str = "\x1b[1m";
+const SHARED: &str = "\x1b[1;7m";
+const RESET: &str = "\x1b[0m";
+
 pub fn run() {
     let file = fs::read_to_string("./inputs/star_fifteen.txt").unwrap();
     let (antennas, w, h) = parse_input(&file);
@@ -9,20 +14,55 @@ pub fn run() {
     println!("Result: {}", result);
 }
 
+pub fn run_report(plain: bool) {
+    let file = fs::read_to_string("./inputs/star_fifteen.txt").unwrap();
+    let (antennas, w, h) = parse_input(&file);
+    print_report(&antennas, Spacing::Ratio { far: 2, near: 1 }, w, h, !plain);
+}
+
 #[derive(Clone, Copy, PartialEq, Debug, Hash, Eq)]
-struct Antenna {
-    frequency: char,
-    x: isize,
-    y: isize,
+pub(crate) struct Antenna {
+    pub frequency: char,
+    pub x: isize,
+    pub y: isize,
 }
 
 #[derive(Clone, Copy, PartialEq, Debug, Hash, Eq)]
-struct Antinode {
-    x: isize,
-    y: isize,
+pub(crate) struct Antinode {
+    pub x: isize,
+    pub y: isize,
+}
+
+// Where antinodes go on the line through two antennas of the same frequency
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Spacing {
+    // Points outside the pair where one antenna is `far / near` times as far
+    // away as the other. Part 1 is 2:1; ratios that don't land on a grid
+    // point give nothing.
+    Ratio { far: isize, near: isize },
+    // The antennas themselves and every step of the difference between them
+    // in both directions, as in part 2
+    Harmonics,
+    // Every grid point on the line, including the ones between the antennas
+    // and the ones a difference like (2,4) steps over
+    Lattice,
 }
 
-fn parse_input(input: &String) -> (Vec<Antenna>, usize, usize) {
+// Reads "2:1" style ratios, "harmonics" or "lattice". Ratios need the near
+// antenna at least one step away and the far one further out.
+pub(crate) fn parse_spacing(spec: &str) -> Option<Spacing> {
+    match spec {
+        "harmonics" => Some(Spacing::Harmonics),
+        "lattice" => Some(Spacing::Lattice),
+        _ => {
+            let (far, near) = spec.split_once(':')?;
+            let (far, near) = (far.parse().ok()?, near.parse().ok()?);
+            (near >= 1 && far > near).then_some(Spacing::Ratio { far, near })
+        },
+    }
+}
+
+pub(crate) fn parse_input(input: &String) -> (Vec<Antenna>, usize, usize) {
     let w = input.lines().next().unwrap().len();
     let h = input.lines().count();
 
@@ -49,80 +89,197 @@ fn parse_input(input: &String) -> (Vec<Antenna>, usize, usize) {
     (antennas, w, h)
 }
 
-fn create_antinodes(antenna: &Antenna, target_antenna: &Antenna) -> Vec<Antinode> {
-    if antenna.frequency != target_antenna.frequency {
-        return vec![];
+pub(crate) fn group_by_frequency(antennas: &[Antenna]) -> BTreeMap<char, Vec<Antenna>> {
+    let mut groups: BTreeMap<char, Vec<Antenna>> = BTreeMap::new();
+    for antenna in antennas {
+        groups.entry(antenna.frequency).or_default().push(*antenna);
     }
+    groups
+}
 
-    if antenna.y > target_antenna.y {
-        return create_antinodes(target_antenna, antenna);
+// Antinodes of a single pair that fall inside the map. Both antennas are
+// expected to share a frequency and sit on different cells.
+pub(crate) fn create_antinodes(antenna: &Antenna, target_antenna: &Antenna, spacing: Spacing, w: usize, h: usize) -> Vec<Antinode> {
+    let is_inside = |antinode: &Antinode| {
+        antinode.x >= 0 && antinode.x < w as isize && antinode.y >= 0 && antinode.y < h as isize
+    };
+    let dx = antenna.x - target_antenna.x;
+    let dy = antenna.y - target_antenna.y;
+
+    match spacing {
+        Spacing::Ratio { far, near } => {
+            // Widened so large ratios can't overflow; steps longer than the
+            // map can't land on it.
+            let (near, gap) = (near as i128, far as i128 - near as i128);
+            let (dx, dy) = (dx as i128, dy as i128);
+            if gap <= 0 || (dx * near) % gap != 0 || (dy * near) % gap != 0 {
+                return vec![];
+            }
+            let (step_x, step_y) = (dx * near / gap, dy * near / gap);
+            if step_x.abs() >= w as i128 || step_y.abs() >= h as i128 {
+                return vec![];
+            }
+            let (step_x, step_y) = (step_x as isize, step_y as isize);
+            [
+                Antinode { x: antenna.x + step_x, y: antenna.y + step_y },
+                Antinode { x: target_antenna.x - step_x, y: target_antenna.y - step_y },
+            ]
+                .into_iter()
+                .filter(is_inside)
+                .collect()
+        },
+        Spacing::Harmonics => {
+            let mut antinodes = vec![
+                Antinode { x: antenna.x, y: antenna.y },
+                Antinode { x: target_antenna.x, y: target_antenna.y },
+            ];
+            for (start, step_x, step_y) in [(antenna, dx, dy), (target_antenna, -dx, -dy)] {
+                let mut antinode = Antinode { x: start.x + step_x, y: start.y + step_y };
+                while is_inside(&antinode) {
+                    antinodes.push(antinode);
+                    antinode = Antinode { x: antinode.x + step_x, y: antinode.y + step_y };
+                }
+            }
+            antinodes
+        },
+        Spacing::Lattice => {
+            let divisor = gcd(dx.unsigned_abs(), dy.unsigned_abs()) as isize;
+            let (step_x, step_y) = (dx / divisor, dy / divisor);
+            let mut antinodes = vec![Antinode { x: antenna.x, y: antenna.y }];
+            for (step_x, step_y) in [(step_x, step_y), (-step_x, -step_y)] {
+                let mut antinode = Antinode { x: antenna.x + step_x, y: antenna.y + step_y };
+                while is_inside(&antinode) {
+                    antinodes.push(antinode);
+                    antinode = Antinode { x: antinode.x + step_x, y: antinode.y + step_y };
+                }
+            }
+            antinodes
+        },
     }
+}
 
-    let point_x = (target_antenna.x as f64 - antenna.x as f64).powf(2.0);
-    let point_y = (target_antenna.y as f64 - antenna.y as f64).powf(2.0);
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
 
-    let distance = (point_x + point_y * 1.0).sqrt();
-    if distance.floor() < 1.0 {
-        return vec![];
+pub(crate) fn group_antinodes(group: &[Antenna], spacing: Spacing, w: usize, h: usize) -> HashSet<Antinode> {
+    let mut antinodes = HashSet::new();
+    for (index, antenna) in group.iter().enumerate() {
+        for target_antenna in &group[index + 1..] {
+            antinodes.extend(create_antinodes(antenna, target_antenna, spacing, w, h));
+        }
     }
+    antinodes
+}
 
-    let vector = Antinode{
-        x: antenna.x - target_antenna.x,
-        y: antenna.y - target_antenna.y,
-    };
+// Only pairs within a frequency group are tried, each of them once
+pub(crate) fn find_antinodes(antennas: &[Antenna], spacing: Spacing, w: usize, h: usize) -> HashSet<Antinode> {
+    group_by_frequency(antennas)
+        .values()
+        .flat_map(|group| group_antinodes(group, spacing, w, h))
+        .collect()
+}
 
-    vec![
-        Antinode{
-            x: antenna.x + vector.x,
-            y: antenna.y + vector.y,
-        },
-        Antinode{
-            x: antenna.x - (vector.x * 2),
-            y: antenna.y - (vector.y * 2),
-        },
-    ]
+fn count_antinodes(antennas: &[Antenna], w: usize, h: usize) -> usize {
+    find_antinodes(antennas, Spacing::Ratio { far: 2, near: 1 }, w, h).len()
 }
 
-fn count_antinodes(antennas: &Vec<Antenna>, w: usize, h: usize) -> usize {
-    let mut all_antinodes = vec![];
-    for antenna in antennas {
-        for target_antenna in antennas {
-            let antinodes = create_antinodes(antenna, target_antenna);
-            all_antinodes.extend(antinodes);
+pub(crate) struct AntinodeReport {
+    pub w: usize,
+    pub h: usize,
+    pub antennas: Vec<Antenna>,
+    pub by_frequency: BTreeMap<char, HashSet<Antinode>>,
+    // The frequencies with an antinode on each cell, in order
+    pub cells: HashMap<Antinode, Vec<char>>,
+}
+
+impl AntinodeReport {
+    pub fn new(antennas: &[Antenna], spacing: Spacing, w: usize, h: usize) -> AntinodeReport {
+        let by_frequency: BTreeMap<char, HashSet<Antinode>> = group_by_frequency(antennas)
+            .into_iter()
+            .map(|(frequency, group)| (frequency, group_antinodes(&group, spacing, w, h)))
+            .collect();
+
+        let mut cells: HashMap<Antinode, Vec<char>> = HashMap::new();
+        for (frequency, antinodes) in &by_frequency {
+            for antinode in antinodes {
+                cells.entry(*antinode).or_default().push(*frequency);
+            }
         }
+
+        AntinodeReport { w, h, antennas: antennas.to_vec(), by_frequency, cells }
+    }
+
+    pub fn total(&self) -> usize {
+        self.cells.len()
     }
 
-    print_antinodes(&all_antinodes, w, h);
+    // Cells with antinodes from more than one frequency, top to bottom
+    pub fn shared_cells(&self) -> Vec<(Antinode, &Vec<char>)> {
+        let mut shared: Vec<(Antinode, &Vec<char>)> = self.cells.iter()
+            .filter(|(_, frequencies)| frequencies.len() > 1)
+            .map(|(antinode, frequencies)| (*antinode, frequencies))
+            .collect();
+        shared.sort_by_key(|(antinode, _)| (antinode.y, antinode.x));
+        shared
+    }
 
-    let mut antinodes_map = HashMap::new();
-    for antinode in all_antinodes {
-        if
-            antinode.x >= (w as isize) || antinode.y >= (h as isize) ||
-            antinode.x < 0 || antinode.y < 0
-        {
-            continue;
+    pub fn summary(&self) -> String {
+        let mut lines = vec![];
+        for (frequency, antinodes) in &self.by_frequency {
+            let antennas = self.antennas.iter().filter(|antenna| antenna.frequency == *frequency).count();
+            lines.push(format!("Frequency {}: {} antennas, {} antinodes", frequency, antennas, antinodes.len()));
+        }
+        let shared = self.shared_cells();
+        lines.push(format!("Shared cells: {}", shared.len()));
+        for (antinode, frequencies) in shared {
+            let frequencies: Vec<String> = frequencies.iter().map(|frequency| frequency.to_string()).collect();
+            lines.push(format!("  {},{}: {}", antinode.x, antinode.y, frequencies.join(", ")));
         }
-        antinodes_map.insert(antinode, true);
+        lines.push(format!("Total: {}", self.total()));
+        lines.join("\n")
     }
 
-    antinodes_map.values().count()
-}
+    // Antinodes are `#`, or `*` when several frequencies share the cell, and
+    // antennas are drawn over them. With colour every frequency gets its own
+    // and shared cells are inverted.
+    pub fn render(&self, color: bool) -> String {
+        let palette: HashMap<char, &str> = self.by_frequency.keys()
+            .zip(PALETTE.iter().cycle())
+            .map(|(frequency, code)| (*frequency, *code))
+            .collect();
+        let mut grid = vec![".".to_string(); self.w * self.h];
 
-fn print_antinodes(antinodes: &Vec<Antinode>, w: usize, h: usize) {
-    for y in 0..h {
-        for x in 0..w {
-            let mut char = '.';
-            for antinode in antinodes {
-                if antinode.x == x as isize && antinode.y == y as isize {
-                    char = '#';
-                }
-            }
-            print!("{}", char);
+        for (antinode, frequencies) in &self.cells {
+            let cell = &mut grid[antinode.y as usize * self.w + antinode.x as usize];
+            *cell = match (frequencies.as_slice(), color) {
+                ([_], false) => "#".to_string(),
+                ([frequency], true) => format!("{}#{}", palette[frequency], RESET),
+                (_, false) => "*".to_string(),
+                (_, true) => format!("{}*{}", SHARED, RESET),
+            };
         }
-        print!("\n");
+        for antenna in &self.antennas {
+            let cell = &mut grid[antenna.y as usize * self.w + antenna.x as usize];
+            *cell = match color {
+                true => format!("{}{}{}{}", BOLD, palette[&antenna.frequency], antenna.frequency, RESET),
+                false => antenna.frequency.to_string(),
+            };
+        }
+
+        grid.chunks(self.w)
+            .map(|row| row.concat())
+            .collect::<Vec<String>>()
+            .join("\n")
     }
 }
 
+pub(crate) fn print_report(antennas: &[Antenna], spacing: Spacing, w: usize, h: usize, color: bool) {
+    let report = AntinodeReport::new(antennas, spacing, w, h);
+    println!("{}", report.render(color));
+    println!();
+    println!("{}", report.summary());
+}
 
 #[cfg(test)]
 mod tests {
@@ -134,7 +291,7 @@ mod tests {
         let antenna_b = Antenna{frequency: 'a', x: 5, y: 5};
 
         assert_eq!(
-            create_antinodes(&antenna_a, &antenna_b),
+            create_antinodes(&antenna_a, &antenna_b, Spacing::Ratio { far: 2, near: 1 }, 12, 12),
             vec![Antinode{x: 3, y: 1}, Antinode{x: 6, y: 7}],
         );
 
@@ -142,7 +299,7 @@ mod tests {
         let antenna_b = Antenna{frequency: 'a', x: 4, y: 5};
 
         assert_eq!(
-            create_antinodes(&antenna_a, &antenna_b),
+            create_antinodes(&antenna_a, &antenna_b, Spacing::Ratio { far: 2, near: 1 }, 12, 12),
             vec![Antinode{x: 6, y: 1}, Antinode{x: 3, y: 7}],
         );
 
@@ -150,11 +307,150 @@ mod tests {
         let antenna_b = Antenna{frequency: '0', x: 5, y: 2};
 
         assert_eq!(
-            create_antinodes(&antenna_a, &antenna_b),
+            create_antinodes(&antenna_a, &antenna_b, Spacing::Ratio { far: 2, near: 1 }, 12, 12),
             vec![Antinode{x: 11, y: 0}, Antinode{x: 2, y: 3}],
         );
     }
 
+    #[test]
+    fn test_create_antinodes_with_other_ratios() {
+        let antenna_a = Antenna{frequency: 'a', x: 4, y: 4};
+        let antenna_b = Antenna{frequency: 'a', x: 6, y: 5};
+        let ratio = |far, near| Spacing::Ratio { far, near };
+
+        // Off the map on the far side
+        assert_eq!(create_antinodes(&antenna_a, &antenna_b, ratio(2, 1), 8, 8), vec![Antinode{x: 2, y: 3}]);
+        // Half a step, which isn't a grid point for y
+        assert_eq!(create_antinodes(&antenna_a, &antenna_b, ratio(3, 1), 12, 12), vec![]);
+        assert_eq!(
+            create_antinodes(&antenna_a, &antenna_b, ratio(3, 2), 12, 12),
+            vec![Antinode{x: 0, y: 2}, Antinode{x: 10, y: 7}],
+        );
+        assert_eq!(create_antinodes(&antenna_a, &antenna_b, ratio(1, 1), 12, 12), vec![]);
+        assert_eq!(create_antinodes(&antenna_a, &antenna_b, ratio(isize::MAX, isize::MAX - 1), 12, 12), vec![]);
+        assert_eq!(
+            create_antinodes(&antenna_a, &antenna_b, ratio(isize::MAX - 1, (isize::MAX - 1) / 2), 12, 12),
+            vec![Antinode{x: 2, y: 3}, Antinode{x: 8, y: 6}],
+        );
+
+        let antenna_b = Antenna{frequency: 'a', x: 6, y: 6};
+        assert_eq!(
+            create_antinodes(&antenna_a, &antenna_b, ratio(3, 1), 12, 12),
+            vec![Antinode{x: 3, y: 3}, Antinode{x: 7, y: 7}],
+        );
+    }
+
+    #[test]
+    fn test_parse_spacing() {
+        assert_eq!(parse_spacing("2:1"), Some(Spacing::Ratio { far: 2, near: 1 }));
+        assert_eq!(parse_spacing("harmonics"), Some(Spacing::Harmonics));
+        assert_eq!(parse_spacing("lattice"), Some(Spacing::Lattice));
+        assert_eq!(parse_spacing("2"), None);
+        assert_eq!(parse_spacing("a:1"), None);
+        assert_eq!(parse_spacing("2:0"), None);
+        assert_eq!(parse_spacing("-1:-3"), None);
+        assert_eq!(parse_spacing("1:1"), None);
+        assert_eq!(parse_spacing("1:2"), None);
+        assert_eq!(parse_spacing("3:2"), Some(Spacing::Ratio { far: 3, near: 2 }));
+    }
+
+    #[test]
+    fn test_group_by_frequency() {
+        let antennas = [
+            Antenna{frequency: 'b', x: 0, y: 0},
+            Antenna{frequency: 'A', x: 1, y: 0},
+            Antenna{frequency: 'b', x: 2, y: 0},
+        ];
+        let groups = group_by_frequency(&antennas);
+        assert_eq!(groups.keys().collect::<Vec<_>>(), [&'A', &'b']);
+        assert_eq!(groups[&'b'], [antennas[0], antennas[2]]);
+    }
+
+    #[test]
+    fn test_find_antinodes_ignores_other_frequencies() {
+        let antennas = [
+            Antenna{frequency: 'a', x: 2, y: 2},
+            Antenna{frequency: 'b', x: 3, y: 3},
+        ];
+        assert!(find_antinodes(&antennas, Spacing::Ratio { far: 2, near: 1 }, 6, 6).is_empty());
+        assert!(find_antinodes(&antennas, Spacing::Harmonics, 6, 6).is_empty());
+    }
+
+    #[test]
+    fn test_antinode_report() {
+        let input = [
+            "............",
+            "........0...",
+            ".....0......",
+            ".......0....",
+            "....0.......",
+            "......A.....",
+            "............",
+            "............",
+            "........A...",
+            ".........A..",
+            "............",
+            "............",
+        ].join("\n");
+        let (antennas, w, h) = parse_input(&input);
+        let report = AntinodeReport::new(&antennas, Spacing::Ratio { far: 2, near: 1 }, w, h);
+
+        assert_eq!(report.by_frequency[&'0'].len(), 10);
+        assert_eq!(report.by_frequency[&'A'].len(), 5);
+        assert_eq!(report.total(), 14);
+        assert_eq!(report.shared_cells(), [(Antinode{x: 3, y: 1}, &vec!['0', 'A'])]);
+        assert_eq!(
+            report.summary(),
+            [
+                "Frequency 0: 4 antennas, 10 antinodes",
+                "Frequency A: 3 antennas, 5 antinodes",
+                "Shared cells: 1",
+                "  3,1: 0, A",
+                "Total: 14",
+            ].join("\n"),
+        );
+        assert_eq!(
+            report.render(false),
+            [
+                "......#....#",
+                "...*....0...",
+                "....#0....#.",
+                "..#....0....",
+                "....0....#..",
+                ".#....A.....",
+                "...#........",
+                "#......#....",
+                "........A...",
+                ".........A..",
+                "..........#.",
+                "..........#.",
+            ].join("\n"),
+        );
+    }
+
+    #[test]
+    fn test_render_antinodes_in_color() {
+        let antennas = [
+            Antenna{frequency: 'a', x: 1, y: 0},
+            Antenna{frequency: 'a', x: 2, y: 0},
+            Antenna{frequency: 'b', x: 2, y: 1},
+            Antenna{frequency: 'b', x: 1, y: 2},
+        ];
+        let report = AntinodeReport::new(&antennas, Spacing::Ratio { far: 2, near: 1 }, 4, 3);
+        let (red, green) = (PALETTE[0], PALETTE[1]);
+
+        assert_eq!(report.shared_cells(), [(Antinode{x: 3, y: 0}, &vec!['a', 'b'])]);
+        assert_eq!(report.render(false), ["#aa*", "..b.", ".b.."].join("\n"));
+        assert_eq!(
+            report.render(true),
+            [
+                format!("{red}#{RESET}{BOLD}{red}a{RESET}{BOLD}{red}a{RESET}{SHARED}*{RESET}"),
+                format!("..{BOLD}{green}b{RESET}."),
+                format!(".{BOLD}{green}b{RESET}.."),
+            ].join("\n"),
+        );
+    }
+
     #[test]
     fn test_count_antinodes() {
         let antennas = vec![
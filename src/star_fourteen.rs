@@ -1,4 +1,8 @@
-use std::fs;
+use std::{collections::{HashMap, HashSet}, fmt, fs, ops::{Add, BitXor, Mul as MulOp}, rc::Rc, str::FromStr, time::Instant};
+
+use num_bigint::{BigUint, ParseBigIntError};
+
+const OPERATORS: [&dyn CalibrationOperator; 3] = [&Sum, &Mul, &Concat { base: 10 }];
 
 pub fn run() {
     let file = fs::read_to_string("./inputs/star_fourteen.txt").unwrap();
@@ -7,8 +11,73 @@ pub fn run() {
     println!("Result: {}", result);
 }
 
+pub fn run_bench() {
+    let file = fs::read_to_string("./inputs/star_fourteen.txt").unwrap();
+    let equations: Vec<(Number, Vec<Number>)> = file.lines().map(|s| parse_formula(&s.to_string())).collect();
+
+    let start = Instant::now();
+    let by_enumeration: Number = equations.iter()
+        .filter_map(|(expected_result, operands)| compute_formula_by_enumeration(expected_result, operands, &OPERATORS))
+        .sum();
+    let enumeration_time = start.elapsed();
+
+    let start = Instant::now();
+    let by_solver: Number = equations.iter()
+        .filter_map(|(expected_result, operands)| compute_formula(expected_result.clone(), operands))
+        .sum();
+    let solver_time = start.elapsed();
+
+    assert_eq!(by_enumeration, by_solver);
+    println!("Enumeration: {:?}", enumeration_time);
+    println!("Backward solver: {:?}", solver_time);
+    println!("Result: {}", by_solver);
+}
+
+pub fn run_ops(spec: &str) {
+    let Some(operators) = parse_operators(spec) else {
+        eprintln!("Unknown operator in {:?}, expected a list of +, *, -, /, ^, || or ||<base>", spec);
+        return;
+    };
+    let operators: Vec<&dyn CalibrationOperator> = operators.iter().map(|operator| operator.as_ref()).collect();
+    let file = fs::read_to_string("./inputs/star_fourteen.txt").unwrap();
+    let result: Number = file.lines()
+        .map(|s| parse_formula(&s.to_string()))
+        .filter(|(expected_result, operands)| can_solve(expected_result, operands, &operators))
+        .map(|(expected_result, _)| expected_result)
+        .sum();
+
+    let symbols: Vec<String> = operators.iter().map(|operator| operator.symbol()).collect();
+    println!("Operators: {}", symbols.join(" "));
+    println!("Result: {}", result);
+}
+
+// mode is "one" for a single expression per solvable line, "all" for every
+// expression, or "count" for the number of expressions on each line
+pub fn run_explain(mode: &str, spec: &str) {
+    let Some(operators) = parse_operators(spec) else {
+        eprintln!("Unknown operator in {:?}, expected a list of +, *, -, /, ^, || or ||<base>", spec);
+        return;
+    };
+    let operators: Vec<&dyn CalibrationOperator> = operators.iter().map(|operator| operator.as_ref()).collect();
+    let file = fs::read_to_string("./inputs/star_fourteen.txt").unwrap();
+
+    for line in file.lines() {
+        let (expected_result, operands) = parse_formula(&line.to_string());
+        let mut search = ExpressionSearch::new(&expected_result, &operands, &operators);
+        match mode {
+            "count" => println!("{}: {}", line, search.count()),
+            "all" => for expression in search.expressions(usize::MAX) {
+                println!("{}", render_expression(&expected_result, &operands, &operators, &expression));
+            },
+            _ => if let Some(expression) = search.expressions(1).pop() {
+                println!("{}", render_expression(&expected_result, &operands, &operators, &expression));
+            },
+        }
+    }
+}
+
 // result = 204976636995111
-fn process_input<'a, I>(str_lines: I) -> isize 
+fn process_input<'a, I>(str_lines: I) -> Number 
 where
     I: IntoIterator<Item = &'a str>
 {
@@ -17,11 +86,11 @@ where
         .map(|(expected_result, operands)| compute_formula(expected_result, &operands))
         .filter(|result| result.is_some())
         .map(|result| result.unwrap())
-        .reduce(|acc, e| acc + e)
-        .unwrap_or(0)
+        .reduce(|acc, e| &acc + &e)
+        .unwrap_or(Number::Small(0))
 }
 
-fn parse_formula(str_formula: &String) -> (isize, Vec<isize>) {
+fn parse_formula(str_formula: &String) -> (Number, Vec<Number>) {
     let mut split = str_formula.split(":");
     let expected_result = split
         .next()
@@ -38,74 +107,899 @@ fn parse_formula(str_formula: &String) -> (isize, Vec<isize>) {
     (expected_result, nums)
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-enum Operator {
-    Sum,
-    Mul,
-    Concat,
+// A non negative integer that stays in a u128 while it fits and moves to a
+// BigUint once it doesn't. Values are always kept in the smaller of the two,
+// so the derived comparisons and hashes agree with the numeric ones.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum Number {
+    Small(u128),
+    Big(BigUint),
+}
+
+impl Number {
+    fn from_big(big: BigUint) -> Number {
+        match u128::try_from(&big) {
+            Ok(small) => Number::Small(small),
+            Err(_) => Number::Big(big),
+        }
+    }
+
+    fn to_big(&self) -> BigUint {
+        match self {
+            Number::Small(small) => BigUint::from(*small),
+            Number::Big(big) => big.clone(),
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == Number::Small(0)
+    }
+
+    pub fn checked_sub(&self, other: &Number) -> Option<Number> {
+        match (self, other) {
+            (Number::Small(left), Number::Small(right)) => left.checked_sub(*right).map(Number::Small),
+            _ if self >= other => Some(Number::from_big(self.to_big() - other.to_big())),
+            _ => None,
+        }
+    }
+
+    pub fn checked_div(&self, other: &Number) -> Option<Number> {
+        match (self, other) {
+            _ if other.is_zero() => None,
+            (Number::Small(left), Number::Small(right)) => Some(Number::Small(left / right)),
+            _ => Some(Number::from_big(self.to_big() / other.to_big())),
+        }
+    }
+
+    pub fn checked_rem(&self, other: &Number) -> Option<Number> {
+        match (self, other) {
+            _ if other.is_zero() => None,
+            (Number::Small(left), Number::Small(right)) => Some(Number::Small(left % right)),
+            _ => Some(Number::from_big(self.to_big() % other.to_big())),
+        }
+    }
+}
+
+impl From<u128> for Number {
+    fn from(value: u128) -> Number {
+        Number::Small(value)
+    }
+}
+
+impl FromStr for Number {
+    type Err = ParseBigIntError;
+
+    fn from_str(s: &str) -> Result<Number, ParseBigIntError> {
+        match s.parse() {
+            Ok(small) => Ok(Number::Small(small)),
+            Err(_) => Ok(Number::from_big(s.parse()?)),
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Number::Small(small) => write!(f, "{}", small),
+            Number::Big(big) => write!(f, "{}", big),
+        }
+    }
+}
+
+impl Add for &Number {
+    type Output = Number;
+
+    fn add(self, other: &Number) -> Number {
+        if let (Number::Small(left), Number::Small(right)) = (self, other) {
+            if let Some(sum) = left.checked_add(*right) {
+                return Number::Small(sum);
+            }
+        }
+        Number::from_big(self.to_big() + other.to_big())
+    }
+}
+
+impl MulOp for &Number {
+    type Output = Number;
+
+    fn mul(self, other: &Number) -> Number {
+        if let (Number::Small(left), Number::Small(right)) = (self, other) {
+            if let Some(product) = left.checked_mul(*right) {
+                return Number::Small(product);
+            }
+        }
+        Number::from_big(self.to_big() * other.to_big())
+    }
+}
+
+impl BitXor for &Number {
+    type Output = Number;
+
+    fn bitxor(self, other: &Number) -> Number {
+        if let (Number::Small(left), Number::Small(right)) = (self, other) {
+            return Number::Small(left ^ right);
+        }
+        Number::from_big(self.to_big() ^ other.to_big())
+    }
+}
+
+impl std::iter::Sum for Number {
+    fn sum<I: Iterator<Item = Number>>(iter: I) -> Number {
+        iter.fold(Number::Small(0), |acc, e| &acc + &e)
+    }
+}
+
+pub(crate) enum Inverse {
+    Impossible,
+    Left(Number),
+    // Every left operand gives the result, like multiplying by zero
+    AnyLeft,
+}
+
+// Values are kept non negative, as they are in the puzzle, so `apply` returns
+// None for results below zero as well as for undefined results. Operators
+// that can't be undone return None from `invert` and the equations using them
+// are searched forwards instead.
+pub(crate) trait CalibrationOperator {
+    fn symbol(&self) -> String;
+    // Only used when evaluating with precedence, where higher levels bind first
+    fn precedence(&self) -> u8;
+    // Whether, for positive operands, the result is never below either operand
+    // and never drops when either operand grows, which lets the precedence
+    // solver drop states that already evaluate past the target
+    fn is_growing(&self) -> bool {
+        false
+    }
+    fn apply(&self, left: &Number, right: &Number) -> Option<Number>;
+    fn invert(&self, _result: &Number, _right: &Number) -> Option<Inverse> {
+        None
+    }
+}
+
+pub(crate) struct Sum;
+pub(crate) struct Mul;
+pub(crate) struct Sub;
+pub(crate) struct Div;
+pub(crate) struct Xor;
+pub(crate) struct Concat {
+    pub base: u128,
+}
+
+impl CalibrationOperator for Sum {
+    fn is_growing(&self) -> bool {
+        true
+    }
+
+    fn symbol(&self) -> String {
+        "+".to_string()
+    }
+
+    fn precedence(&self) -> u8 {
+        1
+    }
+
+    fn apply(&self, left: &Number, right: &Number) -> Option<Number> {
+        Some(left + right)
+    }
+
+    fn invert(&self, result: &Number, right: &Number) -> Option<Inverse> {
+        Some(match result.checked_sub(right) {
+            Some(left) => Inverse::Left(left),
+            None => Inverse::Impossible,
+        })
+    }
+}
+
+impl CalibrationOperator for Mul {
+    fn is_growing(&self) -> bool {
+        true
+    }
+
+    fn symbol(&self) -> String {
+        "*".to_string()
+    }
+
+    fn precedence(&self) -> u8 {
+        2
+    }
+
+    fn apply(&self, left: &Number, right: &Number) -> Option<Number> {
+        Some(left * right)
+    }
+
+    fn invert(&self, result: &Number, right: &Number) -> Option<Inverse> {
+        Some(match result.checked_rem(right) {
+            None if result.is_zero() => Inverse::AnyLeft,
+            Some(remainder) if remainder.is_zero() => Inverse::Left(result.checked_div(right)?),
+            _ => Inverse::Impossible,
+        })
+    }
+}
+
+impl CalibrationOperator for Sub {
+    fn symbol(&self) -> String {
+        "-".to_string()
+    }
+
+    fn precedence(&self) -> u8 {
+        1
+    }
+
+    fn apply(&self, left: &Number, right: &Number) -> Option<Number> {
+        left.checked_sub(right)
+    }
+
+    fn invert(&self, result: &Number, right: &Number) -> Option<Inverse> {
+        Some(Inverse::Left(result + right))
+    }
+}
+
+// Rounds down, so many left operands share a result and there's no inverse
+impl CalibrationOperator for Div {
+    fn symbol(&self) -> String {
+        "/".to_string()
+    }
+
+    fn precedence(&self) -> u8 {
+        2
+    }
+
+    fn apply(&self, left: &Number, right: &Number) -> Option<Number> {
+        left.checked_div(right)
+    }
+}
+
+impl CalibrationOperator for Xor {
+    fn symbol(&self) -> String {
+        "^".to_string()
+    }
+
+    fn precedence(&self) -> u8 {
+        0
+    }
+
+    fn apply(&self, left: &Number, right: &Number) -> Option<Number> {
+        Some(left ^ right)
+    }
+
+    fn invert(&self, result: &Number, right: &Number) -> Option<Inverse> {
+        Some(Inverse::Left(result ^ right))
+    }
+}
+
+impl CalibrationOperator for Concat {
+    fn is_growing(&self) -> bool {
+        true
+    }
+
+    fn symbol(&self) -> String {
+        if self.base == 10 {
+            return "||".to_string();
+        }
+        format!("||{}", self.base)
+    }
+
+    fn precedence(&self) -> u8 {
+        3
+    }
+
+    fn apply(&self, left: &Number, right: &Number) -> Option<Number> {
+        Some(&(left * &digit_magnitude(right, self.base)) + right)
+    }
+
+    fn invert(&self, result: &Number, right: &Number) -> Option<Inverse> {
+        Some(match strip_suffix(result, right, self.base) {
+            Some(left) => Inverse::Left(left),
+            None => Inverse::Impossible,
+        })
+    }
+}
+
+// Moves an operator to another precedence level and leaves it alone otherwise
+pub(crate) struct WithPrecedence {
+    pub operator: Box<dyn CalibrationOperator>,
+    pub precedence: u8,
+}
+
+impl CalibrationOperator for WithPrecedence {
+    fn symbol(&self) -> String {
+        self.operator.symbol()
+    }
+
+    fn precedence(&self) -> u8 {
+        self.precedence
+    }
+
+    fn apply(&self, left: &Number, right: &Number) -> Option<Number> {
+        self.operator.apply(left, right)
+    }
+
+    fn invert(&self, result: &Number, right: &Number) -> Option<Inverse> {
+        self.operator.invert(result, right)
+    }
+
+    fn is_growing(&self) -> bool {
+        self.operator.is_growing()
+    }
+}
+
+// Reads a comma separated list like "+,*,||" or "-,/,^,||2", where a number
+// after "||" picks the base to concatenate in. A level after "@", like
+// "||@1", overrides the operator's precedence; the defaults are 0 for ^,
+// 1 for + and -, 2 for * and / and 3 for concatenation.
+pub(crate) fn parse_operators(spec: &str) -> Option<Vec<Box<dyn CalibrationOperator>>> {
+    spec.split(',')
+        .map(|symbol| -> Option<Box<dyn CalibrationOperator>> {
+            if let Some((symbol, level)) = symbol.split_once('@') {
+                return Some(Box::new(WithPrecedence {
+                    operator: parse_operators(symbol)?.pop()?,
+                    precedence: level.trim().parse().ok()?,
+                }));
+            }
+            match symbol.trim() {
+                "+" => Some(Box::new(Sum)),
+                "*" => Some(Box::new(Mul)),
+                "-" => Some(Box::new(Sub)),
+                "/" => Some(Box::new(Div)),
+                "^" => Some(Box::new(Xor)),
+                "||" => Some(Box::new(Concat { base: 10 })),
+                symbol => {
+                    let base = symbol.strip_prefix("||")?.parse().ok()?;
+                    if base < 2 {
+                        return None;
+                    }
+                    Some(Box::new(Concat { base }))
+                },
+            }
+        })
+        .collect()
+}
+
+fn compute_formula(expected_result: Number, operands: &Vec<Number>) -> Option<Number> {
+    if can_solve(&expected_result, operands, &OPERATORS) {
+        return Some(expected_result);
+    }
+    None
+}
+
+// Works from the last operand back to the first, undoing each operator on
+// the target. Branches that can't be undone are dropped straight away, and
+// targets that were already tried for the same prefix aren't tried again.
+// As soon as an operator has no inverse the whole equation is searched
+// forwards instead.
+pub(crate) fn can_solve(expected_result: &Number, operands: &[Number], operators: &[&dyn CalibrationOperator]) -> bool {
+    let mut pending = vec![(expected_result.clone(), operands.len())];
+    let mut seen = HashSet::new();
+
+    while let Some((target, len)) = pending.pop() {
+        if len == 0 || !seen.insert((target.clone(), len)) {
+            continue;
+        }
+        let operand = &operands[len - 1];
+        if len == 1 {
+            if target == *operand {
+                return true;
+            }
+            continue;
+        }
+
+        for operator in operators {
+            match operator.invert(&target, operand) {
+                Some(Inverse::Left(left)) => pending.push((left, len - 1)),
+                Some(Inverse::AnyLeft) => if search_forwards(&operands[..len - 1], operators, |_| true) {
+                    return true;
+                },
+                Some(Inverse::Impossible) => {},
+                None => return search_forwards(operands, operators, |result| result == expected_result),
+            }
+        }
+    }
+
+    false
+}
+
+// Applies every operator from the first operand on, skipping values that were
+// already reached at the same position
+fn search_forwards<F>(operands: &[Number], operators: &[&dyn CalibrationOperator], accept: F) -> bool
+where
+    F: Fn(&Number) -> bool
+{
+    let Some(first) = operands.first() else {
+        return false;
+    };
+    let mut pending = vec![(first.clone(), 1)];
+    let mut seen = HashSet::new();
+
+    while let Some((value, len)) = pending.pop() {
+        if !seen.insert((value.clone(), len)) {
+            continue;
+        }
+        if len == operands.len() {
+            if accept(&value) {
+                return true;
+            }
+            continue;
+        }
+        for operator in operators {
+            if let Some(next) = operator.apply(&value, &operands[len]) {
+                pending.push((next, len + 1));
+            }
+        }
+    }
+
+    false
+}
+
+// Operator indices for the gaps between operands, from left to right
+pub(crate) type Expression = Vec<usize>;
+
+// Counts and lists the expressions that solve an equation. The backward states
+// are (target, prefix length) pairs like in can_solve and their counts are None
+// once an operator turns out to have no inverse, in which case the forward
+// states (value, prefix length, end) are used instead. A forward search to an
+// end short of the last operand accepts any value, which is what multiplying
+// by zero needs.
+pub(crate) struct ExpressionSearch<'a> {
+    expected_result: &'a Number,
+    operands: &'a [Number],
+    operators: &'a [&'a dyn CalibrationOperator],
+    backward_counts: HashMap<(Number, usize), Option<usize>>,
+    forward_counts: HashMap<(Number, usize, usize), usize>,
+}
+
+impl<'a> ExpressionSearch<'a> {
+    pub fn new(expected_result: &'a Number, operands: &'a [Number], operators: &'a [&'a dyn CalibrationOperator]) -> ExpressionSearch<'a> {
+        ExpressionSearch {
+            expected_result,
+            operands,
+            operators,
+            backward_counts: HashMap::new(),
+            forward_counts: HashMap::new(),
+        }
+    }
+
+    pub fn count(&mut self) -> usize {
+        let len = self.operands.len();
+        if len == 0 {
+            return 0;
+        }
+        match self.count_backward(self.expected_result, len) {
+            Some(count) => count,
+            None => self.count_forward(&self.operands[0], 1, len),
+        }
+    }
+
+    pub fn expressions(&mut self, limit: usize) -> Vec<Expression> {
+        let mut found = vec![];
+        let len = self.operands.len();
+        if self.count() == 0 {
+            return found;
+        }
+        let mut gaps = vec![0; len - 1];
+        if self.count_backward(self.expected_result, len).is_some() {
+            self.expand_backward(self.expected_result, len, &mut gaps, &mut found, limit);
+        } else {
+            self.expand_forward(&self.operands[0], 1, len, &mut gaps, &mut found, limit);
+        }
+        found
+    }
+
+    fn count_backward(&mut self, target: &Number, len: usize) -> Option<usize> {
+        if let Some(count) = self.backward_counts.get(&(target.clone(), len)) {
+            return *count;
+        }
+        let operand = &self.operands[len - 1];
+        let count = if len == 1 {
+            Some((target == operand) as usize)
+        } else {
+            let mut count = Some(0_usize);
+            for operator in self.operators {
+                let ways = match operator.invert(target, operand) {
+                    Some(Inverse::Left(left)) => self.count_backward(&left, len - 1),
+                    Some(Inverse::AnyLeft) => Some(self.count_forward(&self.operands[0], 1, len - 1)),
+                    Some(Inverse::Impossible) => Some(0),
+                    None => None,
+                };
+                count = count.zip(ways).map(|(count, ways)| count.saturating_add(ways));
+            }
+            count
+        };
+        self.backward_counts.insert((target.clone(), len), count);
+        count
+    }
+
+    fn count_forward(&mut self, value: &Number, len: usize, end: usize) -> usize {
+        if len == end {
+            return (end < self.operands.len() || value == self.expected_result) as usize;
+        }
+        if let Some(count) = self.forward_counts.get(&(value.clone(), len, end)) {
+            return *count;
+        }
+        let mut count = 0_usize;
+        for operator in self.operators {
+            if let Some(next) = operator.apply(value, &self.operands[len]) {
+                count = count.saturating_add(self.count_forward(&next, len + 1, end));
+            }
+        }
+        self.forward_counts.insert((value.clone(), len, end), count);
+        count
+    }
+
+    // `gaps` holds one operator per gap and is filled in from both ends, so
+    // every complete expression is a copy of it
+    fn expand_backward(&mut self, target: &Number, len: usize, gaps: &mut Expression, found: &mut Vec<Expression>, limit: usize) {
+        if found.len() >= limit || self.count_backward(target, len) == Some(0) {
+            return;
+        }
+        if len == 1 {
+            found.push(gaps.clone());
+            return;
+        }
+        let operand = &self.operands[len - 1];
+        for (index, operator) in self.operators.iter().enumerate() {
+            gaps[len - 2] = index;
+            match operator.invert(target, operand) {
+                Some(Inverse::Left(left)) => self.expand_backward(&left, len - 1, gaps, found, limit),
+                Some(Inverse::AnyLeft) => self.expand_forward(&self.operands[0], 1, len - 1, gaps, found, limit),
+                _ => {},
+            }
+        }
+    }
+
+    fn expand_forward(&mut self, value: &Number, len: usize, end: usize, gaps: &mut Expression, found: &mut Vec<Expression>, limit: usize) {
+        if found.len() >= limit || self.count_forward(value, len, end) == 0 {
+            return;
+        }
+        if len == end {
+            found.push(gaps.clone());
+            return;
+        }
+        for (index, operator) in self.operators.iter().enumerate() {
+            if let Some(next) = operator.apply(value, &self.operands[len]) {
+                gaps[len - 1] = index;
+                self.expand_forward(&next, len + 1, end, gaps, found, limit);
+            }
+        }
+    }
+}
+
+pub(crate) fn render_expression(expected_result: &Number, operands: &[Number], operators: &[&dyn CalibrationOperator], expression: &Expression) -> String {
+    let mut rendered = operands.first().map(|operand| operand.to_string()).unwrap_or_default();
+    for (operand, operator) in operands.iter().skip(1).zip(expression) {
+        rendered += &format!(" {} {}", operators[*operator].symbol(), operand);
+    }
+    format!("{} = {}", rendered, expected_result)
 }
 
-fn compute_formula(expected_result: isize, operands: &Vec<isize>) -> Option<isize> {
+// Evaluation state for precedence mode. Values waiting on an operator that
+// binds looser than the ones after it are kept in `pending`, with that
+// operator's index, so their levels only go up towards the top.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct PrecedenceStack {
+    pending: Vec<(Number, usize)>,
+    current: Number,
+}
+
+impl PrecedenceStack {
+    fn new(first: &Number) -> PrecedenceStack {
+        PrecedenceStack {
+            pending: vec![],
+            current: first.clone(),
+        }
+    }
+
+    fn push(&self, operators: &[&dyn CalibrationOperator], operator: usize, operand: &Number) -> Option<PrecedenceStack> {
+        let mut next = self.clone();
+        let level = operators[operator].precedence();
+        next.reduce(operators, |pending| operators[pending].precedence() >= level)?;
+        next.pending.push((next.current, operator));
+        next.current = operand.clone();
+        Some(next)
+    }
+
+    fn finish(mut self, operators: &[&dyn CalibrationOperator]) -> Option<Number> {
+        self.reduce(operators, |_| true)?;
+        Some(self.current)
+    }
+
+    fn reduce<F>(&mut self, operators: &[&dyn CalibrationOperator], should_reduce: F) -> Option<()>
+    where
+        F: Fn(usize) -> bool
+    {
+        while let Some((_, operator)) = self.pending.last() {
+            if !should_reduce(*operator) {
+                break;
+            }
+            let (left, operator) = self.pending.pop()?;
+            self.current = operators[operator].apply(&left, &self.current)?;
+        }
+        Some(())
+    }
+}
+
+pub(crate) fn evaluate_with_precedence(operands: &[Number], operators: &[&dyn CalibrationOperator], expression: &Expression) -> Option<Number> {
+    let mut stack = PrecedenceStack::new(operands.first()?);
+    for (operand, operator) in operands.iter().skip(1).zip(expression) {
+        stack = stack.push(operators, *operator, operand)?;
+    }
+    stack.finish(operators)
+}
+
+pub(crate) fn solve_with_precedence(expected_result: &Number, operands: &[Number], operators: &[&dyn CalibrationOperator]) -> Option<Expression> {
+    if operands.is_empty() {
+        return None;
+    }
+    let mut solver = PrecedenceSolver::new(expected_result, operands, operators);
+    match solver.solve(expected_result, operands.len(), 0) {
+        Ok(expression) => expression,
+        Err(NoInverse) => search_precedence_forwards(expected_result, operands, operators),
+    }
+}
+
+// When every operator grows and no operand is zero, no part of an expression
+// can be worth more than the whole of it
+fn is_bounded(operands: &[Number], operators: &[&dyn CalibrationOperator]) -> bool {
+    operators.iter().all(|operator| operator.is_growing())
+        && operands.iter().all(|operand| !operand.is_zero())
+}
+
+struct NoInverse;
+
+// Splits expressions at their loosest operators: what's left is a left to
+// right chain of terms that only use tighter ones. The values the last term
+// can take are worked out on their own and undone from the target like in
+// can_solve, which leaves the same problem for the operands before it.
+struct PrecedenceSolver<'a> {
+    operands: &'a [Number],
+    operators: &'a [&'a dyn CalibrationOperator],
+    levels: Vec<u8>,
+    bound: Option<Number>,
+    // Every value of operands[start..end] using levels from `level` up, with
+    // one expression for each
+    terms: HashMap<(usize, usize, usize), Rc<HashMap<Number, Expression>>>,
+    failed: HashSet<(Number, usize, usize)>,
+}
+
+impl<'a> PrecedenceSolver<'a> {
+    fn new(expected_result: &Number, operands: &'a [Number], operators: &'a [&'a dyn CalibrationOperator]) -> PrecedenceSolver<'a> {
+        let mut levels: Vec<u8> = operators.iter().map(|operator| operator.precedence()).collect();
+        levels.sort();
+        levels.dedup();
+
+        PrecedenceSolver {
+            operands,
+            operators,
+            levels,
+            bound: is_bounded(operands, operators).then(|| expected_result.clone()),
+            terms: HashMap::new(),
+            failed: HashSet::new(),
+        }
+    }
+
+    fn operators_at(&self, level: usize) -> impl Iterator<Item = (usize, &'a dyn CalibrationOperator)> + '_ {
+        let operators = self.operators;
+        operators.iter()
+            .copied()
+            .enumerate()
+            .filter(move |(_, operator)| operator.precedence() == self.levels[level])
+    }
+
+    // Solves operands[..end] using levels from `level` up
+    fn solve(&mut self, target: &Number, end: usize, level: usize) -> Result<Option<Expression>, NoInverse> {
+        if level == self.levels.len() {
+            return Ok((end == 1 && *target == self.operands[0]).then(Vec::new));
+        }
+        if self.failed.contains(&(target.clone(), end, level)) {
+            return Ok(None);
+        }
+        if let Some(expression) = self.solve(target, end, level + 1)? {
+            return Ok(Some(expression));
+        }
+
+        let operators: Vec<_> = self.operators_at(level).collect();
+        for start in (1..end).rev() {
+            let terms = self.terms(start, end, level + 1);
+            for (value, term) in terms.iter() {
+                for (index, operator) in &operators {
+                    let prefix = match operator.invert(target, value) {
+                        Some(Inverse::Left(left)) => self.solve(&left, start, level)?,
+                        Some(Inverse::AnyLeft) => self.terms(0, start, level).values().next().cloned(),
+                        Some(Inverse::Impossible) => None,
+                        None => return Err(NoInverse),
+                    };
+                    if let Some(mut expression) = prefix {
+                        expression.push(*index);
+                        expression.extend(term);
+                        return Ok(Some(expression));
+                    }
+                }
+            }
+        }
+
+        self.failed.insert((target.clone(), end, level));
+        Ok(None)
+    }
+
+    fn terms(&mut self, start: usize, end: usize, level: usize) -> Rc<HashMap<Number, Expression>> {
+        if let Some(terms) = self.terms.get(&(start, end, level)) {
+            return terms.clone();
+        }
+
+        let mut terms = HashMap::new();
+        if level == self.levels.len() {
+            if end - start == 1 {
+                terms.insert(self.operands[start].clone(), vec![]);
+            }
+        } else {
+            terms = (*self.terms(start, end, level + 1)).clone();
+            let operators: Vec<_> = self.operators_at(level).collect();
+            for split in start + 1..end {
+                let lefts = self.terms(start, split, level);
+                let rights = self.terms(split, end, level + 1);
+                for ((left, left_expression), (right, right_expression)) in lefts.iter().flat_map(|left| rights.iter().map(move |right| (left, right))) {
+                    for (index, operator) in &operators {
+                        let Some(value) = operator.apply(left, right) else {
+                            continue;
+                        };
+                        if self.bound.as_ref().is_some_and(|bound| value > *bound) {
+                            continue;
+                        }
+                        terms.entry(value).or_insert_with(|| {
+                            let mut expression = left_expression.clone();
+                            expression.push(*index);
+                            expression.extend(right_expression);
+                            expression
+                        });
+                    }
+                }
+            }
+        }
+
+        let terms = Rc::new(terms);
+        self.terms.insert((start, end, level), terms.clone());
+        terms
+    }
+}
+
+// Tries every operator forwards like search_forwards, but on whole evaluation
+// states since a value can still change once a tighter operator follows it.
+// Appending operands to a bounded expression can't make it evaluate to less,
+// so states already past the target are dropped.
+fn search_precedence_forwards(expected_result: &Number, operands: &[Number], operators: &[&dyn CalibrationOperator]) -> Option<Expression> {
+    let can_prune = is_bounded(operands, operators);
+    let mut pending = vec![(PrecedenceStack::new(operands.first()?), vec![])];
+    let mut seen = HashSet::new();
+
+    while let Some((stack, expression)) = pending.pop() {
+        let len = expression.len() + 1;
+        if can_prune && stack.clone().finish(operators).is_none_or(|value| value > *expected_result) {
+            continue;
+        }
+        if !seen.insert((stack.clone(), len)) {
+            continue;
+        }
+        if len == operands.len() {
+            if stack.finish(operators).as_ref() == Some(expected_result) {
+                return Some(expression);
+            }
+            continue;
+        }
+        for operator in 0..operators.len() {
+            if let Some(next) = stack.push(operators, operator, &operands[len]) {
+                let mut expression = expression.clone();
+                expression.push(operator);
+                pending.push((next, expression));
+            }
+        }
+    }
+
+    None
+}
+
+// Prints the left to right and precedence totals next to each other, along
+// with every line that only one of them can solve
+pub(crate) fn report_precedence(input: &str, operators: &[&dyn CalibrationOperator]) {
+    let mut left_to_right = Number::Small(0);
+    let mut with_precedence = Number::Small(0);
+
+    for line in input.lines() {
+        let (expected_result, operands) = parse_formula(&line.to_string());
+        let in_order = ExpressionSearch::new(&expected_result, &operands, operators).expressions(1).pop();
+        let by_precedence = solve_with_precedence(&expected_result, &operands, operators);
+
+        if let Some(expression) = &by_precedence {
+            debug_assert_eq!(evaluate_with_precedence(&operands, operators, expression).as_ref(), Some(&expected_result), "{}", line);
+            with_precedence = &with_precedence + &expected_result;
+        }
+        if in_order.is_some() {
+            left_to_right = &left_to_right + &expected_result;
+        }
+        if in_order.is_some() != by_precedence.is_some() {
+            let render = |expression: Option<Expression>| expression
+                .map_or("-".to_string(), |expression| render_expression(&expected_result, &operands, operators, &expression));
+            println!("{:<48} {:<48} {}", line, render(in_order), render(by_precedence));
+        }
+    }
+
+    println!("Left to right: {}", left_to_right);
+    println!("Precedence:    {}", with_precedence);
+}
+
+pub fn run_precedence(spec: &str) {
+    let Some(operators) = parse_operators(spec) else {
+        eprintln!("Unknown operator in {:?}, expected a list of +, *, -, /, ^, || or ||<base>", spec);
+        return;
+    };
+    let operators: Vec<&dyn CalibrationOperator> = operators.iter().map(|operator| operator.as_ref()).collect();
+    let file = fs::read_to_string("./inputs/star_fourteen.txt").unwrap();
+    report_precedence(&file, &operators);
+}
+
+fn digit_magnitude(number: &Number, base: u128) -> Number {
+    let base = Number::Small(base);
+    let mut magnitude = base.clone();
+    while magnitude <= *number {
+        magnitude = &magnitude * &base;
+    }
+    magnitude
+}
+
+fn strip_suffix(number: &Number, suffix: &Number, base: u128) -> Option<Number> {
+    let magnitude = digit_magnitude(suffix, base);
+    if number.checked_rem(&magnitude)? == *suffix {
+        return number.checked_div(&magnitude);
+    }
+    None
+}
+
+fn compute_formula_by_enumeration(expected_result: &Number, operands: &[Number], operators: &[&dyn CalibrationOperator]) -> Option<Number> {
     let mut current_operators = initialize_operators(operands.len());
-    let max_attempts = 3_usize.pow((operands.len() as u32) - 1);
+    let max_attempts = operators.len().pow((operands.len() as u32) - 1);
 
     assert_eq!(current_operators.len(), operands.len());
 
     for _ in 0..max_attempts {
-        let mut result = 0;
+        let mut result = None;
         let mut is_first = true;
 
         for (operand, operator) in operands.iter().zip(&current_operators) {
             if is_first {
-                result = *operand;
+                result = Some(operand.clone());
                 is_first = false;
                 continue;
             }
-            match operator {
-                Operator::Mul => result *= *operand,
-                Operator::Sum => result += *operand,
-                Operator::Concat => result = format!("{}{}", result, *operand).parse().unwrap(),
-            };
+            result = result.and_then(|result| operators[*operator].apply(&result, operand));
 
-            if result > expected_result {
+            if result.is_none() {
                 break;
             }
         }
 
-        if result == expected_result {
-            return Some(result);
+        if result.as_ref() == Some(expected_result) {
+            return result;
         }
 
-        cycle_operators(&mut current_operators);
+        cycle_operators(&mut current_operators, operators.len());
 
     }
 
     None
 }
 
-fn initialize_operators(len: usize) -> Vec<Operator> {
-    vec![Operator::Sum; len]
+fn initialize_operators(len: usize) -> Vec<usize> {
+    vec![0; len]
 }
 
-fn cycle_operators(operators: &mut Vec<Operator>) {
+// Counts through operator indices like an odometer, skipping the first slot
+// since no operator comes before the first operand
+fn cycle_operators(operators: &mut Vec<usize>, operator_count: usize) {
     let mut is_done = false;
     let mut current_index = 1;
 
-    // Sum = 0, Mul = 1, Concat = 2
     while !is_done && current_index < operators.len() {
-        let operator = operators[current_index];
-        let new_operator = match operator {
-            Operator::Sum => Operator::Mul,
-            Operator::Mul => Operator::Concat,
-            Operator::Concat => Operator::Sum,
-        };
-
-        operators[current_index] = new_operator;
+        operators[current_index] = (operators[current_index] + 1) % operator_count;
 
-        is_done = match operator {
-            Operator::Sum | Operator::Mul => true,
-            Operator::Concat => false,
-        };
+        is_done = operators[current_index] != 0;
 
         if !is_done {
             current_index += 1;
@@ -114,11 +1008,14 @@ fn cycle_operators(operators: &mut Vec<Operator>) {
     }
 }
 
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn numbers(values: &[u128]) -> Vec<Number> {
+        values.iter().map(|value| Number::from(*value)).collect()
+    }
+
     #[test]
     fn test_process_input() {
         let input = vec![
@@ -132,50 +1029,268 @@ mod tests {
             "21037: 9 7 18 13",
             "292: 11 6 16 20",
         ];
-        assert_eq!(process_input(input), 11387);
+        assert_eq!(process_input(input), Number::from(11387));
     }
 
     #[test]
     fn test_cycle_operators() {
-        let mut operators = vec![Operator::Sum, Operator::Sum, Operator::Sum];
-        cycle_operators(&mut operators);
-        assert_eq!(operators, vec![Operator::Sum, Operator::Mul, Operator::Sum]);
-        cycle_operators(&mut operators);
-        assert_eq!(operators, vec![Operator::Sum, Operator::Concat, Operator::Sum]);
-        cycle_operators(&mut operators);
-        assert_eq!(operators, vec![Operator::Sum, Operator::Sum, Operator::Mul]);
-        cycle_operators(&mut operators);
-        assert_eq!(operators, vec![Operator::Sum, Operator::Mul, Operator::Mul]);
-        cycle_operators(&mut operators);
-        assert_eq!(operators, vec![Operator::Sum, Operator::Concat, Operator::Mul]);
-        cycle_operators(&mut operators);
-        assert_eq!(operators, vec![Operator::Sum, Operator::Sum, Operator::Concat]);
+        let mut operators = vec![0, 0, 0];
+        cycle_operators(&mut operators, 3);
+        assert_eq!(operators, vec![0, 1, 0]);
+        cycle_operators(&mut operators, 3);
+        assert_eq!(operators, vec![0, 2, 0]);
+        cycle_operators(&mut operators, 3);
+        assert_eq!(operators, vec![0, 0, 1]);
+        cycle_operators(&mut operators, 3);
+        assert_eq!(operators, vec![0, 1, 1]);
+        cycle_operators(&mut operators, 3);
+        assert_eq!(operators, vec![0, 2, 1]);
+        cycle_operators(&mut operators, 3);
+        assert_eq!(operators, vec![0, 0, 2]);
     }
 
 
     #[test]
     fn test_compute_formula() {
-        assert_eq!(compute_formula(190, &vec![10, 19]), Some(190));
-        assert_eq!(compute_formula(3267, &vec![81, 40, 27]), Some(3267));
-        assert_eq!(compute_formula(83, &vec![17, 5]), None);
-        assert_eq!(compute_formula(156, &vec![15, 6]), Some(156));
-        assert_eq!(compute_formula(7290, &vec![6, 8, 6, 15]), Some(7290));
-        assert_eq!(compute_formula(161011, &vec![16, 10, 13]), None);
-        assert_eq!(compute_formula(192, &vec![17, 8, 14]), Some(192));
-        assert_eq!(compute_formula(21037, &vec![9, 7, 18, 13]), None);
-        assert_eq!(compute_formula(292, &vec![11, 6, 16, 20]), Some(292));
+        assert_eq!(compute_formula(Number::from(190), &numbers(&[10, 19])), Some(Number::from(190)));
+        assert_eq!(compute_formula(Number::from(3267), &numbers(&[81, 40, 27])), Some(Number::from(3267)));
+        assert_eq!(compute_formula(Number::from(83), &numbers(&[17, 5])), None);
+        assert_eq!(compute_formula(Number::from(156), &numbers(&[15, 6])), Some(Number::from(156)));
+        assert_eq!(compute_formula(Number::from(7290), &numbers(&[6, 8, 6, 15])), Some(Number::from(7290)));
+        assert_eq!(compute_formula(Number::from(161011), &numbers(&[16, 10, 13])), None);
+        assert_eq!(compute_formula(Number::from(192), &numbers(&[17, 8, 14])), Some(Number::from(192)));
+        assert_eq!(compute_formula(Number::from(21037), &numbers(&[9, 7, 18, 13])), None);
+        assert_eq!(compute_formula(Number::from(292), &numbers(&[11, 6, 16, 20])), Some(Number::from(292)));
+    }
+
+    #[test]
+    fn test_compute_formula_by_enumeration() {
+        assert_eq!(compute_formula_by_enumeration(&Number::from(190), &numbers(&[10, 19]), &OPERATORS), Some(Number::from(190)));
+        assert_eq!(compute_formula_by_enumeration(&Number::from(83), &numbers(&[17, 5]), &OPERATORS), None);
+        assert_eq!(compute_formula_by_enumeration(&Number::from(7290), &numbers(&[6, 8, 6, 15]), &OPERATORS), Some(Number::from(7290)));
+        assert_eq!(compute_formula_by_enumeration(&Number::from(21037), &numbers(&[9, 7, 18, 13]), &OPERATORS), None);
+        assert_eq!(compute_formula_by_enumeration(&Number::from(3), &numbers(&[7, 2, 2]), &[&Sub, &Div]), Some(Number::from(3)));
+    }
+
+    #[test]
+    fn test_can_solve() {
+        assert!(can_solve(&Number::from(156), &numbers(&[15, 6]), &OPERATORS));
+        assert!(!can_solve(&Number::from(156), &numbers(&[15, 6]), &[&Sum, &Mul]));
+        assert!(can_solve(&Number::from(0), &numbers(&[5, 3, 0]), &[&Mul]));
+        assert!(can_solve(&Number::from(5), &numbers(&[0, 5]), &[&Concat { base: 10 }]));
+        assert!(!can_solve(&Number::from(5), &numbers(&[]), &OPERATORS));
+
+        // 40 ones can only add up to 40, far past what the old u16 mask covered
+        let operands = numbers(&[1; 40]);
+        assert!(can_solve(&Number::from(40), &operands, &[&Sum, &Mul]));
+        assert!(!can_solve(&Number::from(41), &operands, &[&Sum, &Mul]));
+        assert!(can_solve(&Number::from(1_111_111_111), &operands[..10], &OPERATORS));
+    }
+
+    #[test]
+    fn test_can_solve_with_other_operators() {
+        // 7 - 2 = 5, 5 / 2 = 2 can't be undone, so this is searched forwards
+        assert!(can_solve(&Number::from(2), &numbers(&[7, 2, 2]), &[&Sub, &Div]));
+        assert!(!can_solve(&Number::from(4), &numbers(&[7, 2, 2]), &[&Sub, &Div]));
+        assert!(can_solve(&Number::from(6), &numbers(&[3, 5]), &[&Xor]));
+        assert!(can_solve(&Number::from(0), &numbers(&[3, 5, 8]), &[&Sub, &Sum]));
+        // 2 - 3 would go below zero
+        assert!(!can_solve(&Number::from(4), &numbers(&[2, 3, 5]), &[&Sub, &Sum]));
+        // 0b101 || 0b11 = 0b10111
+        assert!(can_solve(&Number::from(23), &numbers(&[5, 3]), &[&Concat { base: 2 }]));
+        assert!(!can_solve(&Number::from(53), &numbers(&[5, 3]), &[&Concat { base: 2 }]));
+        // Anything times zero is zero
+        assert!(can_solve(&Number::from(0), &numbers(&[5, 9, 0]), &[&Sub, &Mul]));
+    }
+
+    #[test]
+    fn test_expression_search() {
+        let render = |expected_result, operands: &[u128], operators: &[&dyn CalibrationOperator], limit| {
+            let expected_result = Number::from(expected_result);
+            let operands = numbers(operands);
+            ExpressionSearch::new(&expected_result, &operands, operators)
+                .expressions(limit)
+                .iter()
+                .map(|expression| render_expression(&expected_result, &operands, operators, expression))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(render(3267, &[81, 40, 27], &OPERATORS, 1), ["81 * 40 + 27 = 3267"]);
+        assert_eq!(render(3267, &[81, 40, 27], &OPERATORS, usize::MAX), ["81 * 40 + 27 = 3267", "81 + 40 * 27 = 3267"]);
+        assert_eq!(render(7290, &[6, 8, 6, 15], &OPERATORS, usize::MAX), ["6 * 8 || 6 * 15 = 7290"]);
+        assert!(render(83, &[17, 5], &OPERATORS, usize::MAX).is_empty());
+        assert_eq!(render(5, &[5], &OPERATORS, usize::MAX), ["5 = 5"]);
+        // Division has no inverse, so these come from the forward search
+        assert_eq!(render(2, &[7, 2, 2], &[&Sub, &Div], usize::MAX), ["7 - 2 / 2 = 2"]);
+        assert_eq!(render(1, &[7, 2, 2], &[&Sub, &Div], usize::MAX), ["7 / 2 - 2 = 1", "7 / 2 / 2 = 1"]);
+        assert_eq!(render(0, &[5, 9, 0], &[&Sum, &Mul], usize::MAX), ["5 + 9 * 0 = 0", "5 * 9 * 0 = 0"]);
+
+        assert_eq!(ExpressionSearch::new(&Number::from(3267), &numbers(&[81, 40, 27]), &OPERATORS).count(), 2);
+        assert_eq!(ExpressionSearch::new(&Number::from(1), &numbers(&[7, 2, 2]), &[&Sub, &Div]).count(), 2);
+        assert_eq!(ExpressionSearch::new(&Number::from(0), &numbers(&[]), &OPERATORS).count(), 0);
+        // Only sums reach 4 and only products stay at 1
+        assert_eq!(ExpressionSearch::new(&Number::from(4), &numbers(&[1, 1, 1, 1]), &[&Sum, &Mul]).count(), 1);
+        assert_eq!(ExpressionSearch::new(&Number::from(1), &numbers(&[1; 8]), &[&Sum, &Mul]).count(), 1);
+        // Multiplying by the final zero works for all 2^38 ways to combine the ones
+        let mut operands = numbers(&[1; 40]);
+        operands[39] = Number::from(0);
+        assert_eq!(ExpressionSearch::new(&Number::from(0), &operands, &[&Sum, &Mul]).count(), 1 << 38);
+    }
+
+    #[test]
+    fn test_evaluate_with_precedence() {
+        let operands = numbers(&[6, 8, 6, 15]);
+        let evaluate = |spec, expression: &[usize]| {
+            let operators = parse_operators(spec).unwrap();
+            let operators: Vec<&dyn CalibrationOperator> = operators.iter().map(|operator| operator.as_ref()).collect();
+            evaluate_with_precedence(&operands, &operators, &expression.to_vec())
+        };
+        // 6 * 8 || 6 * 15
+        assert_eq!(evaluate("+,*,||", &[1, 2, 1]), Some(Number::from(7740)));
+        assert_eq!(evaluate("+,*,||@1", &[1, 2, 1]), Some(Number::from(4890)));
+        assert_eq!(evaluate("+,*,||@2", &[1, 2, 1]), Some(Number::from(7290)));
+        // 6 + 8 * 6 + 15
+        assert_eq!(evaluate("+,*", &[0, 1, 0]), Some(Number::from(69)));
+        assert_eq!(evaluate("+,*@0", &[0, 1, 0]), Some(Number::from(294)));
+        // 6 * 8 - 6 * 15 goes below zero, 6 * (8 - 6) * 15 doesn't
+        assert_eq!(evaluate("-,*", &[1, 0, 1]), None);
+        assert_eq!(evaluate("-,*@0", &[1, 0, 1]), Some(Number::from(180)));
+        assert_eq!(evaluate_with_precedence(&[], &OPERATORS, &vec![]), None);
+    }
+
+    #[test]
+    fn test_solve_with_precedence() {
+        let input = [
+            "190: 10 19",
+            "3267: 81 40 27",
+            "83: 17 5",
+            "156: 15 6",
+            "7290: 6 8 6 15",
+            "161011: 16 10 13",
+            "192: 17 8 14",
+            "21037: 9 7 18 13",
+            "292: 11 6 16 20",
+        ];
+        let total = |spec| {
+            let operators = parse_operators(spec).unwrap();
+            let operators: Vec<&dyn CalibrationOperator> = operators.iter().map(|operator| operator.as_ref()).collect();
+            input.iter()
+                .map(|line| parse_formula(&line.to_string()))
+                .filter_map(|(expected_result, operands)| {
+                    let expression = solve_with_precedence(&expected_result, &operands, &operators)?;
+                    assert_eq!(evaluate_with_precedence(&operands, &operators, &expression).as_ref(), Some(&expected_result));
+                    Some(expected_result)
+                })
+                .sum::<Number>()
+        };
+        assert_eq!(total("+,*"), Number::from(3457));
+        assert_eq!(total("+,*,||"), Number::from(3805));
+        assert_eq!(total("+,*,||@1"), Number::from(3805));
+        assert_eq!(total("+,*,||@0"), Number::from(3613));
+
+        let solve = |expected_result, operands: &[u128], operators: &[&dyn CalibrationOperator]| {
+            let expected_result = Number::from(expected_result);
+            let operands = numbers(operands);
+            solve_with_precedence(&expected_result, &operands, operators)
+                .map(|expression| render_expression(&expected_result, &operands, operators, &expression))
+        };
+        assert_eq!(solve(3267, &[81, 40, 27], &OPERATORS), Some("81 * 40 + 27 = 3267".to_string()));
+        assert_eq!(solve(292, &[11, 6, 16, 20], &OPERATORS), None);
+        assert_eq!(solve(5, &[5], &OPERATORS), Some("5 = 5".to_string()));
+        // Division has no inverse, so this is searched forwards
+        assert_eq!(solve(11, &[7, 8, 2], &[&Sum, &Div]), Some("7 + 8 / 2 = 11".to_string()));
+        assert_eq!(solve(7, &[7, 8, 2], &[&Sum, &Div]), None);
+        // The zero wipes out whatever comes before it in the same term
+        assert_eq!(solve(1, &[5, 9, 0, 1], &[&Sum, &Mul]), Some("5 * 9 * 0 + 1 = 1".to_string()));
+        assert_eq!(solve(6, &[5, 9, 0, 1], &[&Sum, &Mul]), Some("5 + 9 * 0 + 1 = 6".to_string()));
+    }
+
+    #[test]
+    fn test_parse_operators() {
+        let symbols = |spec| parse_operators(spec)
+            .map(|operators| operators.iter().map(|operator| operator.symbol()).collect::<Vec<_>>());
+        assert_eq!(symbols("+,*,||"), Some(vec!["+".to_string(), "*".to_string(), "||".to_string()]));
+        assert_eq!(symbols("- , / ,^,||2"), Some(vec!["-".to_string(), "/".to_string(), "^".to_string(), "||2".to_string()]));
+        assert_eq!(symbols("||16"), Some(vec!["||16".to_string()]));
+        assert_eq!(symbols("+,%"), None);
+        assert_eq!(symbols("||1"), None);
+        assert_eq!(symbols("+,||@0"), Some(vec!["+".to_string(), "||".to_string()]));
+        assert_eq!(symbols("+@x"), None);
+        let operators = parse_operators("+,*@0,||2@1").unwrap();
+        let levels: Vec<u8> = operators.iter().map(|operator| operator.precedence()).collect();
+        assert_eq!(levels, [1, 0, 1]);
+    }
+
+    #[test]
+    fn test_concat_apply() {
+        assert_eq!(Concat { base: 10 }.apply(&Number::from(15), &Number::from(6)), Some(Number::from(156)));
+        assert_eq!(Concat { base: 10 }.apply(&Number::from(10), &Number::from(0)), Some(Number::from(100)));
+        assert_eq!(Concat { base: 16 }.apply(&Number::from(1), &Number::from(255)), Some(Number::from(0x1ff)));
+        assert_eq!(
+            Concat { base: 10 }.apply(&Number::from(u128::MAX), &Number::from(1)),
+            Some("3402823669209384634633746074317682114551".parse().unwrap()),
+        );
+    }
+
+    #[test]
+    fn test_number() {
+        let max = Number::from(u128::MAX);
+        let one = Number::from(1);
+        let past_max: Number = "340282366920938463463374607431768211456".parse().unwrap();
+        assert!(matches!(past_max, Number::Big(_)));
+        assert_eq!(&max + &one, past_max);
+        assert_eq!(past_max.checked_sub(&one), Some(max.clone()));
+        assert!(matches!(past_max.checked_sub(&one), Some(Number::Small(_))));
+        assert_eq!(one.checked_sub(&past_max), None);
+        assert_eq!(&past_max * &Number::from(0), Number::from(0));
+        assert_eq!(past_max.checked_div(&Number::from(2)), Some(Number::from(1 << 127)));
+        assert_eq!(past_max.checked_rem(&max), Some(one.clone()));
+        assert_eq!(past_max.checked_div(&Number::from(0)), None);
+        assert_eq!(&past_max ^ &past_max, Number::from(0));
+        assert!(max < past_max);
+        assert_eq!(past_max.to_string(), "340282366920938463463374607431768211456");
+        assert!("12a".parse::<Number>().is_err());
+    }
+
+    #[test]
+    fn test_process_input_past_u128() {
+        let input = [
+            // 2^64 * 2^64 = 2^128
+            "340282366920938463463374607431768211456: 18446744073709551616 18446744073709551616",
+            // 40 digits, joined from two 20 digit operands
+            "1234567890123456789012345678901234567890: 12345678901234567890 12345678901234567890",
+            "1234567890123456789012345678901234567891: 12345678901234567890 12345678901234567890",
+            "190: 10 19",
+        ];
+        assert_eq!(
+            process_input(input),
+            "1574850257044395252475720286333002779536".parse().unwrap(),
+        );
+        // The product leaves u128 before the subtraction brings it back
+        assert!(can_solve(&Number::from(u128::MAX), &numbers(&[u128::MAX, 2, u128::MAX]), &[&Mul, &Sub]));
+        assert!(!can_solve(&Number::from(0), &numbers(&[u128::MAX, 2, u128::MAX]), &[&Mul, &Sub]));
+    }
+
+    #[test]
+    fn test_strip_suffix() {
+        assert_eq!(strip_suffix(&Number::from(156), &Number::from(6), 10), Some(Number::from(15)));
+        assert_eq!(strip_suffix(&Number::from(156), &Number::from(56), 10), Some(Number::from(1)));
+        assert_eq!(strip_suffix(&Number::from(156), &Number::from(156), 10), Some(Number::from(0)));
+        assert_eq!(strip_suffix(&Number::from(156), &Number::from(5), 10), None);
+        assert_eq!(strip_suffix(&Number::from(100), &Number::from(0), 10), Some(Number::from(10)));
+        assert_eq!(strip_suffix(&Number::from(1000), &Number::from(10), 10), None);
+        assert_eq!(strip_suffix(&Number::from(0x1ff), &Number::from(0xff), 16), Some(Number::from(1)));
     }
 
     #[test]
     fn test_parse_formula() {
-        assert_eq!(parse_formula(&"190: 10 19".to_string()), (190, vec![10, 19]));
-        assert_eq!(parse_formula(&"3267: 81 40 27".to_string()), (3267, vec![81, 40, 27]));
-        assert_eq!(parse_formula(&"83: 17 5".to_string()), (83, vec![17, 5]));
-        assert_eq!(parse_formula(&"156: 15 6".to_string()), (156, vec![15, 6]));
-        assert_eq!(parse_formula(&"7290: 6 8 6 15".to_string()), (7290, vec![6, 8, 6, 15]));
-        assert_eq!(parse_formula(&"161011: 16 10 13".to_string()), (161011, vec![16, 10, 13]));
-        assert_eq!(parse_formula(&"192: 17 8 14".to_string()), (192, vec![17, 8, 14]));
-        assert_eq!(parse_formula(&"21037: 9 7 18 13".to_string()), (21037, vec![9, 7, 18, 13]));
-        assert_eq!(parse_formula(&"292: 11 6 16 20".to_string()), (292, vec![11, 6, 16, 20]));
+        assert_eq!(parse_formula(&"190: 10 19".to_string()), (Number::from(190), numbers(&[10, 19])));
+        assert_eq!(parse_formula(&"3267: 81 40 27".to_string()), (Number::from(3267), numbers(&[81, 40, 27])));
+        assert_eq!(parse_formula(&"83: 17 5".to_string()), (Number::from(83), numbers(&[17, 5])));
+        assert_eq!(parse_formula(&"156: 15 6".to_string()), (Number::from(156), numbers(&[15, 6])));
+        assert_eq!(parse_formula(&"7290: 6 8 6 15".to_string()), (Number::from(7290), numbers(&[6, 8, 6, 15])));
+        assert_eq!(parse_formula(&"161011: 16 10 13".to_string()), (Number::from(161011), numbers(&[16, 10, 13])));
+        assert_eq!(parse_formula(&"192: 17 8 14".to_string()), (Number::from(192), numbers(&[17, 8, 14])));
+        assert_eq!(parse_formula(&"21037: 9 7 18 13".to_string()), (Number::from(21037), numbers(&[9, 7, 18, 13])));
+        assert_eq!(parse_formula(&"292: 11 6 16 20".to_string()), (Number::from(292), numbers(&[11, 6, 16, 20])));
     }
 }
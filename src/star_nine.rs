@@ -1,15 +1,61 @@
 use std::fs;
 use regex::Regex;
 
+use crate::star_ten::{sort_invalid_update, RuleGraph};
+
 pub fn run() {
     let file = fs::read_to_string("./inputs/star_nine.txt").unwrap();
     let input = file.lines();
     let (rules, updates) = parse_input(input);
-    let valid_updates = get_valid_updates(&rules, &updates);
+    let valid_updates = get_valid_updates(&RuleGraph::from_rules(&rules), &updates);
     let result = sum_updates_mid_numbers(&valid_updates);
     println!("Result: {}", result);
 }
 
+pub fn run_report() {
+    let file = fs::read_to_string("./inputs/star_nine.txt").unwrap();
+    let (rules, updates) = parse_input(file.lines());
+    let graph = RuleGraph::from_rules(&rules);
+    for (index, update) in updates.iter().enumerate() {
+        let violations = find_violations(&graph, update);
+        if violations.is_empty() {
+            continue;
+        }
+        println!("Update {}: {}", index + 1, join_pages(update));
+        for violation in &violations {
+            println!(
+                "  broken {}|{}: {} at position {}, {} at position {}",
+                violation.rule.0, violation.rule.1,
+                violation.rule.0, violation.x_index,
+                violation.rule.1, violation.y_index,
+            );
+        }
+        match sort_invalid_update(&graph, update) {
+            Ok(corrected) => {
+                println!("  corrected: {}", join_pages(&corrected));
+                println!("  moves to corrected: {}", join_pages(&pages_to_move(update, &corrected)));
+            },
+            Err(err) => println!("  {}", err),
+        }
+    }
+}
+
+pub fn run_dot(update_number: Option<&str>) {
+    let file = fs::read_to_string("./inputs/star_nine.txt").unwrap();
+    let (rules, updates) = parse_input(file.lines());
+    let update = match update_number {
+        None => None,
+        Some(number) => match number.parse::<usize>() {
+            Ok(number) if (1..=updates.len()).contains(&number) => Some(updates[number - 1].as_slice()),
+            _ => {
+                eprintln!("Invalid update number {:?}, expected 1 to {}", number, updates.len());
+                return;
+            },
+        },
+    };
+    print!("{}", rules_to_dot(&rules, update));
+}
+
 fn parse_input<'a, I>(str_lines: I) -> (Vec<(isize, isize)>, Vec<Vec<isize>>)
 where
     I: IntoIterator<Item = &'a str>
@@ -41,11 +87,11 @@ fn sum_updates_mid_numbers(valid_updates: &Vec<Vec<isize>>) -> isize {
     result
 }
 
-fn get_valid_updates(rules: &Vec<(isize, isize)>, updates: &Vec<Vec<isize>>) -> Vec<Vec<isize>> {
+fn get_valid_updates(graph: &RuleGraph, updates: &Vec<Vec<isize>>) -> Vec<Vec<isize>> {
     let mut valid_updates = vec![];
 
     for update in updates {
-        if is_update_valid(&rules, update) {
+        if graph.is_update_valid(update) {
             valid_updates.push(update.clone());
         }
     }
@@ -53,24 +99,84 @@ fn get_valid_updates(rules: &Vec<(isize, isize)>, updates: &Vec<Vec<isize>>) ->
     valid_updates
 }
 
-fn is_update_valid(rules: &Vec<(isize, isize)>, update: &Vec<isize>) -> bool {
+#[derive(PartialEq, Debug)]
+struct Violation {
+    rule: (isize, isize),
+    x_index: usize,
+    y_index: usize,
+}
 
-    for (rule_x, rule_y) in rules {
-        let page_x_index = update.iter().position(|v| *v == *rule_x);
-        if page_x_index.is_none() {
-            continue;
+fn find_violations(graph: &RuleGraph, update: &[isize]) -> Vec<Violation> {
+    let mut violations = vec![];
+    for (y_index, page_y) in update.iter().enumerate() {
+        for (x_index, page_x) in update.iter().enumerate().skip(y_index + 1) {
+            if graph.must_precede(*page_x, *page_y) {
+                violations.push(Violation { rule: (*page_x, *page_y), x_index, y_index });
+            }
         }
-        let page_y_index = update.iter().position(|v| *v == *rule_y);
-        if page_y_index.is_none() {
-            continue;
+    }
+    violations
+}
+
+// Pages to move to turn the update into this particular corrected order: the
+// longest subsequence already in that order stays, everything else is moved
+// once. Other valid orders can need fewer moves.
+fn pages_to_move(update: &[isize], corrected: &[isize]) -> Vec<isize> {
+    let positions: Vec<usize> = update.iter()
+        .map(|page| corrected.iter().position(|v| v == page).unwrap())
+        .collect();
+    let mut lengths = vec![1; positions.len()];
+    let mut previous = vec![None; positions.len()];
+    for i in 0..positions.len() {
+        for j in 0..i {
+            if positions[j] < positions[i] && lengths[j] + 1 > lengths[i] {
+                lengths[i] = lengths[j] + 1;
+                previous[i] = Some(j);
+            }
         }
+    }
 
-        if page_x_index.unwrap() > page_y_index.unwrap() {
-            return false
+    let mut kept = vec![false; positions.len()];
+    let mut index = (0..positions.len()).max_by_key(|i| (lengths[*i], usize::MAX - i));
+    while let Some(i) = index {
+        kept[i] = true;
+        index = previous[i];
+    }
+
+    update.iter().zip(kept).filter(|(_, kept)| !kept).map(|(page, _)| *page).collect()
+}
+
+fn rules_to_dot(rules: &[(isize, isize)], update: Option<&[isize]>) -> String {
+    let update = update.unwrap_or(&[]);
+    let graph = RuleGraph::from_rules(rules);
+    let violated: Vec<(isize, isize)> = find_violations(&graph, update).iter().map(|v| v.rule).collect();
+
+    let mut pages: Vec<isize> = rules.iter().flat_map(|(x, y)| [*x, *y]).collect();
+    pages.sort();
+    pages.dedup();
+
+    let mut lines = vec!["digraph rules {".to_string()];
+    for page in pages {
+        if update.contains(&page) {
+            lines.push(format!("    {} [style=filled, fillcolor=lightblue];", page));
+        } else {
+            lines.push(format!("    {};", page));
         }
     }
+    for rule in rules {
+        if violated.contains(rule) {
+            lines.push(format!("    {} -> {} [color=red, penwidth=2];", rule.0, rule.1));
+        } else {
+            lines.push(format!("    {} -> {};", rule.0, rule.1));
+        }
+    }
+    lines.push("}".to_string());
 
-    true
+    lines.join("\n") + "\n"
+}
+
+fn join_pages(pages: &[isize]) -> String {
+    pages.iter().map(|page| page.to_string()).collect::<Vec<String>>().join(",")
 }
 
 fn parse_rule(line: &str) -> Option<(isize, isize)> {
@@ -196,12 +302,107 @@ mod tests {
             (75, 13),
             (53, 13),
         ];
-        assert!(is_update_valid(&rules, &vec![75,47,61,53,29]));
-        assert!(is_update_valid(&rules, &vec![97,61,53,29,13]));
-        assert!(is_update_valid(&rules, &vec![75,29,13]));
-        assert!(!is_update_valid(&rules, &vec![75,97,47,61,53]));
-        assert!(!is_update_valid(&rules, &vec![61,13,29]));
-        assert!(!is_update_valid(&rules, &vec![97,13,75,29,47]));
+        let graph = RuleGraph::from_rules(&rules);
+        assert!(graph.is_update_valid(&[75,47,61,53,29]));
+        assert!(graph.is_update_valid(&[97,61,53,29,13]));
+        assert!(graph.is_update_valid(&[75,29,13]));
+        assert!(!graph.is_update_valid(&[75,97,47,61,53]));
+        assert!(!graph.is_update_valid(&[61,13,29]));
+        assert!(!graph.is_update_valid(&[97,13,75,29,47]));
+    }
+
+    #[test]
+    fn test_find_violations() {
+        let rules = vec![
+            (47, 53),
+            (97, 13),
+            (97, 61),
+            (97, 47),
+            (75, 29),
+            (61, 13),
+            (75, 53),
+            (29, 13),
+            (97, 29),
+            (53, 29),
+            (61, 53),
+            (97, 53),
+            (61, 29),
+            (47, 13),
+            (75, 47),
+            (97, 75),
+            (47, 61),
+            (75, 61),
+            (47, 29),
+            (75, 13),
+            (53, 13),
+        ];
+        let graph = RuleGraph::from_rules(&rules);
+        assert_eq!(find_violations(&graph, &[75,47,61,53,29]), vec![]);
+        assert_eq!(
+            find_violations(&graph, &[75,97,47,61,53]),
+            vec![Violation { rule: (97, 75), x_index: 1, y_index: 0 }],
+        );
+        assert_eq!(
+            find_violations(&graph, &[61,13,29]),
+            vec![Violation { rule: (29, 13), x_index: 2, y_index: 1 }],
+        );
+        assert_eq!(
+            find_violations(&graph, &[97,13,75,29,47]),
+            vec![
+                Violation { rule: (75, 13), x_index: 2, y_index: 1 },
+                Violation { rule: (29, 13), x_index: 3, y_index: 1 },
+                Violation { rule: (47, 13), x_index: 4, y_index: 1 },
+                Violation { rule: (47, 29), x_index: 4, y_index: 3 },
+            ],
+        );
+    }
+
+    #[test]
+    fn test_rules_to_dot() {
+        let rules = vec![
+            (47, 53),
+            (97, 47),
+            (53, 29),
+        ];
+        assert_eq!(
+            rules_to_dot(&rules, None),
+            [
+                "digraph rules {",
+                "    29;",
+                "    47;",
+                "    53;",
+                "    97;",
+                "    47 -> 53;",
+                "    97 -> 47;",
+                "    53 -> 29;",
+                "}",
+                "",
+            ].join("\n"),
+        );
+        assert_eq!(
+            rules_to_dot(&rules, Some(&[53, 47, 97])),
+            [
+                "digraph rules {",
+                "    29;",
+                "    47 [style=filled, fillcolor=lightblue];",
+                "    53 [style=filled, fillcolor=lightblue];",
+                "    97 [style=filled, fillcolor=lightblue];",
+                "    47 -> 53 [color=red, penwidth=2];",
+                "    97 -> 47 [color=red, penwidth=2];",
+                "    53 -> 29;",
+                "}",
+                "",
+            ].join("\n"),
+        );
+    }
+
+    #[test]
+    fn test_pages_to_move() {
+        assert_eq!(pages_to_move(&[75,97,47,61,53], &[97,75,47,61,53]), vec![97]);
+        assert_eq!(pages_to_move(&[61,13,29], &[61,29,13]), vec![29]);
+        assert_eq!(pages_to_move(&[97,13,75,29,47], &[97,75,47,29,13]), vec![13,47]);
+        assert_eq!(pages_to_move(&[1,2,3], &[1,2,3]), vec![]);
+        assert_eq!(pages_to_move(&[3,2,1], &[1,2,3]), vec![2,1]);
     }
 
     #[test]
@@ -238,7 +439,7 @@ mod tests {
             vec![97,13,75,29,47],
         ];
         assert_eq!(
-            get_valid_updates(&rules, &updates),
+            get_valid_updates(&RuleGraph::from_rules(&rules), &updates),
             vec![
                 vec![75,47,61,53,29],
                 vec![97,61,53,29,13],
@@ -1,14 +1,38 @@
-use std::{collections::HashMap, fs};
+use std::{collections::HashMap, fs, time::Instant};
 
 pub fn run() {
     let file = fs::read_to_string("./inputs/star_nineteen.txt").unwrap();
     let (positions, w, h) = parse_input(&file);
-    let result = calculate_total_map_score(&positions, w, h);
+    let result: usize = measure_trailheads(&positions, w, h).iter()
+        .map(|(_, metrics)| metrics.score)
+        .sum();
 
     println!("Result: {}", result);
 }
 
-const DIRECTIONS: [(isize, isize); 4] = [
+pub fn run_bench() {
+    let file = fs::read_to_string("./inputs/star_nineteen.txt").unwrap();
+    let (positions, w, h) = parse_input(&file);
+
+    let start = Instant::now();
+    let scores = calculate_total_map_score(&positions, w, h);
+    let ratings = crate::star_twenty::calculate_total_map_score(&positions, w, h);
+    let search_time = start.elapsed();
+
+    let start = Instant::now();
+    let metrics = measure_trailheads(&positions, w, h);
+    let dp_scores: usize = metrics.iter().map(|(_, metrics)| metrics.score).sum();
+    let dp_ratings: usize = metrics.iter().map(|(_, metrics)| metrics.rating).sum();
+    let dp_time = start.elapsed();
+
+    assert_eq!((scores, ratings), (dp_scores, dp_ratings));
+    println!("Search per trailhead: {:?}", search_time);
+    println!("Levels 9 to 0: {:?}", dp_time);
+    println!("Scores: {}", dp_scores);
+    println!("Ratings: {}", dp_ratings);
+}
+
+pub(crate) const DIRECTIONS: [(isize, isize); 4] = [
     (0, -1), // Up
     (0, 1), // Down
     (-1, 0), // Left
@@ -16,13 +40,19 @@ const DIRECTIONS: [(isize, isize); 4] = [
 ];
 
 #[derive(Copy, Clone, PartialEq, Debug, Eq, Hash)]
-struct Position {
-    x: isize,
-    y: isize,
-    h: isize,
+pub(crate) struct Position {
+    pub x: isize,
+    pub y: isize,
+    pub h: isize,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Eq)]
+pub(crate) struct TrailheadMetrics {
+    pub score: usize,
+    pub rating: usize,
 }
 
-fn parse_input(input: &String) -> (Vec<Position>, usize, usize) {
+pub(crate) fn parse_input(input: &String) -> (Vec<Position>, usize, usize) {
 
     let w = input.lines().next().unwrap().len();
     let h = input.lines().count();
@@ -83,11 +113,68 @@ fn calculate_total_map_score(positions: &Vec<Position>, w: usize, h: usize) -> u
         .sum()
 }
 
+// Works through the map one height at a time, from the summits down to the
+// trailheads. A cell's rating is the sum of the ratings of its neighbours one
+// step higher, and the summits it reaches are the union of theirs, kept as a
+// bitset with one bit per summit. Every cell is handled once, and only the
+// bitsets of the level above are kept around.
+pub(crate) fn measure_trailheads(positions: &[Position], w: usize, h: usize) -> Vec<(Position, TrailheadMetrics)> {
+    let mut levels: Vec<Vec<usize>> = vec![vec![]; 10];
+    for (index, position) in positions.iter().enumerate() {
+        if (0..10).contains(&position.h) {
+            levels[position.h as usize].push(index);
+        }
+    }
+
+    let words = levels[9].len().div_ceil(64);
+    let mut ratings = vec![0; positions.len()];
+    let mut summits: Vec<Vec<u64>> = vec![vec![]; positions.len()];
+    for (bit, &index) in levels[9].iter().enumerate() {
+        ratings[index] = 1;
+        summits[index] = vec![0; words];
+        summits[index][bit / 64] |= 1 << (bit % 64);
+    }
+
+    for height in (0..9).rev() {
+        for &index in &levels[height] {
+            let mut reached = vec![0; words];
+            for next in neighbours(index, w, h) {
+                if positions[next].h == height as isize + 1 {
+                    ratings[index] += ratings[next];
+                    for (word, next_word) in reached.iter_mut().zip(&summits[next]) {
+                        *word |= next_word;
+                    }
+                }
+            }
+            summits[index] = reached;
+        }
+        for &index in &levels[height + 1] {
+            summits[index] = vec![];
+        }
+    }
+
+    levels[0].iter()
+        .map(|&index| {
+            let score = summits[index].iter().map(|word| word.count_ones() as usize).sum();
+            (positions[index], TrailheadMetrics { score, rating: ratings[index] })
+        })
+        .collect()
+}
+
+fn neighbours(index: usize, w: usize, h: usize) -> impl Iterator<Item = usize> {
+    let (x, y) = ((index % w) as isize, (index / w) as isize);
+    DIRECTIONS.iter()
+        .map(move |(dx, dy)| (x + dx, y + dy))
+        .filter(move |&(x, y)| x >= 0 && y >= 0 && (x as usize) < w && (y as usize) < h)
+        .map(move |(x, y)| x as usize + y as usize * w)
+}
+
 
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::star_ten::Lcg;
 
     #[test]
     fn test_parse_input() {
@@ -177,4 +264,59 @@ mod tests {
         let (positions, w, h) = parse_input(&input);
         assert_eq!(calculate_total_map_score(&positions, w, h), 36);
     }
+
+    #[test]
+    fn test_measure_trailheads() {
+        let input = [
+            "89010123",
+            "78121874",
+            "87430965",
+            "96549874",
+            "45678903",
+            "32019012",
+            "01329801",
+            "10456732"
+        ].join("\n");
+        let (positions, w, h) = parse_input(&input);
+        let metrics = measure_trailheads(&positions, w, h);
+        assert_eq!(metrics.len(), 9);
+        assert_eq!(metrics[0], (Position{x: 2, y: 0, h: 0}, TrailheadMetrics{score: 5, rating: 20}));
+        assert_eq!(metrics.iter().map(|(_, metrics)| metrics.score).sum::<usize>(), 36);
+        assert_eq!(metrics.iter().map(|(_, metrics)| metrics.rating).sum::<usize>(), 81);
+
+        let input = [
+            "012345",
+            "123456",
+            "234567",
+            "345678",
+            "406789",
+            "567890",
+        ].join("\n");
+        let (positions, w, h) = parse_input(&input);
+        assert_eq!(
+            measure_trailheads(&positions, w, h)[0],
+            (Position{x: 0, y: 0, h: 0}, TrailheadMetrics{score: 2, rating: 227}),
+        );
+    }
+
+    #[test]
+    fn test_measure_trailheads_matches_search() {
+        let (w, h) = (40, 30);
+        let mut rng = Lcg::new(19);
+        let mut input = String::new();
+        for y in 0..h {
+            for x in 0..w {
+                input.push(char::from_digit(((x + y + rng.below(3)) % 10) as u32, 10).unwrap());
+            }
+            input.push('\n');
+        }
+        let (positions, w, h) = parse_input(&input);
+        let metrics = measure_trailheads(&positions, w, h);
+        assert!(positions.iter().filter(|p| p.h == 9).count() > 64);
+        for (position, metrics) in metrics {
+            let score = calculate_trailhead_score(&position, &positions, &mut HashMap::new(), w, h);
+            let rating = crate::star_twenty::calculate_trailhead_score(&position, &positions, w, h);
+            assert_eq!((metrics.score, metrics.rating), (score, rating), "{:?}", position);
+        }
+    }
 }
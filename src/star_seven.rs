@@ -1,6 +1,12 @@
 use std::fs;
 
 const XMAS: &str = "XMAS";
+const DIRECTIONS: [(isize, isize); 8] = [
+    (1, 0), (-1, 0), (0, 1), (0, -1),
+    (1, 1), (-1, -1), (1, -1), (-1, 1),
+];
+const HIGHLIGHT: &str = "\x1b[1;31m";
+const RESET: &str = "\x1b[0m";
 
 pub fn run() {
     let file = fs::read_to_string("./inputs/star_seven.txt").unwrap();
@@ -8,6 +14,15 @@ pub fn run() {
     println!("Result: {}", result);
 }
 
+pub fn run_render(plain: bool) {
+    let file = fs::read_to_string("./inputs/star_seven.txt").unwrap();
+    let matches = find_matches(&file);
+    println!("{}", render_matches(&file, &matches, !plain));
+    println!();
+    println!("{}", render_heatmap(&match_heatmap(&file, &matches)));
+    println!("Result: {}", matches.len());
+}
+
 fn count_all(input: &String) -> usize {
     count_horizontal(input) +
     count_vertical(input) +
@@ -88,6 +103,77 @@ fn count_horizontal(input: &String) -> usize {
     count
 }
 
+fn find_matches(input: &str) -> Vec<Vec<(usize, usize)>> {
+    let grid: Vec<Vec<char>> = input.lines().map(|line| line.chars().collect()).collect();
+    let target: Vec<char> = XMAS.chars().collect();
+    let mut matches = vec![];
+
+    for (y, row) in grid.iter().enumerate() {
+        for x in 0..row.len() {
+            for (dx, dy) in DIRECTIONS {
+                let mut cells = vec![];
+                for (i, char) in target.iter().enumerate() {
+                    let cx = x as isize + dx * i as isize;
+                    let cy = y as isize + dy * i as isize;
+                    if cx < 0 || cy < 0 {
+                        break;
+                    }
+                    match grid.get(cy as usize).and_then(|r| r.get(cx as usize)) {
+                        Some(c) if c == char => cells.push((cx as usize, cy as usize)),
+                        _ => break,
+                    }
+                }
+                if cells.len() == target.len() {
+                    matches.push(cells);
+                }
+            }
+        }
+    }
+
+    matches
+}
+
+pub(crate) fn match_heatmap(input: &str, matches: &[Vec<(usize, usize)>]) -> Vec<Vec<usize>> {
+    let mut heatmap: Vec<Vec<usize>> = input.lines()
+        .map(|line| vec![0; line.chars().count()])
+        .collect();
+    for cells in matches {
+        for (x, y) in cells {
+            heatmap[*y][*x] += 1;
+        }
+    }
+    heatmap
+}
+
+pub(crate) fn render_matches(input: &str, matches: &[Vec<(usize, usize)>], color: bool) -> String {
+    let heatmap = match_heatmap(input, matches);
+    let mut lines = vec![];
+    for (y, line) in input.lines().enumerate() {
+        let mut rendered = String::new();
+        for (x, char) in line.chars().enumerate() {
+            let matched = heatmap[y][x] > 0;
+            match (matched, color) {
+                (true, true) => rendered.push_str(&format!("{}{}{}", HIGHLIGHT, char, RESET)),
+                (false, false) => rendered.push('.'),
+                _ => rendered.push(char),
+            }
+        }
+        lines.push(rendered);
+    }
+    lines.join("\n")
+}
+
+pub(crate) fn render_heatmap(heatmap: &[Vec<usize>]) -> String {
+    heatmap.iter()
+        .map(|row| row.iter().map(|count| match count {
+            0 => '.',
+            1..=9 => char::from_digit(*count as u32, 10).unwrap(),
+            _ => '+',
+        }).collect::<String>())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -132,6 +218,63 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_find_matches() {
+        let input = [
+            "MMMSXXMASM",
+            "MSAMXMSMSA",
+            "AMXSXMAAMM",
+            "MSAMASMSMX",
+            "XMASAMXAMM",
+            "XXAMMXXAMA",
+            "SMSMSASXSS",
+            "SAXAMASAAA",
+            "MAMMMXMMMM",
+            "MXMXAXMASX"
+        ].join("\n");
+        let matches = find_matches(&input);
+        assert_eq!(matches.len(), count_all(&input));
+        assert!(matches.contains(&vec![(5, 0), (6, 0), (7, 0), (8, 0)]));
+        assert!(matches.contains(&vec![(4, 1), (3, 1), (2, 1), (1, 1)]));
+    }
+
+    #[test]
+    fn test_render_matches() {
+        let input = [
+            "..X...",
+            ".SAMX.",
+            ".A..A.",
+            "XMAS.S",
+            ".X....",
+        ].join("\n");
+        let matches = find_matches(&input);
+        assert_eq!(matches.len(), 4);
+        assert_eq!(
+            render_matches(&input, &matches, false),
+            [
+                "..X...",
+                ".SAMX.",
+                ".A..A.",
+                "XMAS.S",
+                ".X....",
+            ].join("\n"),
+        );
+        assert_eq!(
+            render_matches("XMAS.", &find_matches("XMAS."), true),
+            "\x1b[1;31mX\x1b[0m\x1b[1;31mM\x1b[0m\x1b[1;31mA\x1b[0m\x1b[1;31mS\x1b[0m.",
+        );
+        assert_eq!(render_matches("XMASX", &find_matches("XMASX"), false), "XMAS.");
+    }
+
+    #[test]
+    fn test_match_heatmap() {
+        let input = "XMASAMX".to_string();
+        let matches = find_matches(&input);
+        assert_eq!(match_heatmap(&input, &matches), vec![vec![1, 1, 1, 2, 1, 1, 1]]);
+        assert_eq!(render_heatmap(&match_heatmap(&input, &matches)), "1112111");
+        assert_eq!(render_heatmap(&[vec![0, 3, 12]]), ".3+");
+    }
+
     #[test]
     fn test_count_diagonal() {
         assert_eq!(
@@ -1,14 +1,65 @@
 use std::fs;
+use std::time::Instant;
+
+use crate::star_eighteen::{checksum_files, read_disk_map, unpack_filesystem as unpack_spans, DiskMap, FileSpan};
 
 pub fn run() {
-    let file = fs::read_to_string("./inputs/star_seventeen.txt").unwrap();
-    let unpacked_fs = unpack_filesystem(&file);
-    let defragged_fs = defrag_filesystem(&unpacked_fs);
-    let checksum = calculate_checksum(&defragged_fs);
+    let Some(disk) = read_disk_map("./inputs/star_seventeen.txt") else {
+        return;
+    };
+    let checksum = checksum_files(&compact_blocks(&disk));
 
     println!("Result: {}", checksum);
 }
 
+pub fn run_bench() {
+    let file = fs::read_to_string("./inputs/star_seventeen.txt").unwrap();
+
+    let start = Instant::now();
+    let by_blocks = calculate_checksum(&defrag_filesystem(&unpack_filesystem(&file)));
+    let blocks_time = start.elapsed();
+
+    let start = Instant::now();
+    let disk = DiskMap::from_unpacked(&unpack_spans(&file)).unwrap();
+    let by_spans = checksum_files(&compact_blocks(&disk));
+    let spans_time = start.elapsed();
+
+    assert_eq!(by_blocks as u128, by_spans);
+    println!("Blocks: {:?}", blocks_time);
+    println!("Spans: {:?}", spans_time);
+    println!("Result: {}", by_spans);
+}
+
+// Fills free spans from the left with blocks taken from the end of the
+// rightmost file, splitting files into several spans with the same id where
+// needed. Works on spans only, so memory does not grow with the disk size.
+pub(crate) fn compact_blocks(disk: &DiskMap) -> Vec<FileSpan> {
+    let mut files = disk.files.clone();
+    let mut moved = vec![];
+    let mut free = disk.free.iter().filter(|span| span.len > 0).copied();
+    let mut current_free = free.next();
+
+    while let (Some(mut gap), Some(last)) = (current_free, files.last_mut()) {
+        if gap.start >= last.start {
+            break;
+        }
+
+        let len = gap.len.min(last.len);
+        moved.push(FileSpan { id: last.id, start: gap.start, len });
+        last.len -= len;
+        if last.len == 0 {
+            files.pop();
+        }
+
+        gap.start += len;
+        gap.len -= len;
+        current_free = if gap.len > 0 { Some(gap) } else { free.next() };
+    }
+
+    files.extend(moved);
+    files
+}
+
 fn unpack_filesystem(filesystem: &String) -> Vec<Option<usize>> {
     let mut new_chars = vec![];
     let mut is_white_space = false;
@@ -43,7 +94,7 @@ fn defrag_filesystem(unpacked_filesystem: &Vec<Option<usize>>) -> Vec<Option<usi
 
     while !is_finished {
         // move x from left to right until we find an empty space
-        while new_fs[x] != None && x < unpacked_filesystem.len() {
+        while x < unpacked_filesystem.len() && new_fs[x] != None {
             x += 1;
         }
 
@@ -64,10 +115,9 @@ fn defrag_filesystem(unpacked_filesystem: &Vec<Option<usize>>) -> Vec<Option<usi
 
 fn calculate_checksum(unpacked_filesystem: &Vec<Option<usize>>) -> usize {
     unpacked_filesystem.iter()
-        .map(|c| c.unwrap_or(0))
         .enumerate()
-        .reduce(|acc, (index, val)| (index, acc.1 + (index * val)))
-        .unwrap().1
+        .map(|(index, id)| index * id.unwrap_or(0))
+        .sum()
 }
 
 
@@ -75,6 +125,8 @@ fn calculate_checksum(unpacked_filesystem: &Vec<Option<usize>>) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::star_eighteen::random_disk_map;
+    use crate::star_ten::Lcg;
 
     #[test]
     fn test_unpack_filesystem() {
@@ -137,5 +189,57 @@ mod tests {
             1928,
         );
     }
+
+    #[test]
+    fn test_compact_blocks() {
+        let disk = DiskMap::from_unpacked(&unpack_spans(&"12345".to_string())).unwrap();
+        let mut files = compact_blocks(&disk);
+        files.sort_by_key(|file| file.start);
+        assert_eq!(
+            files,
+            vec![
+                FileSpan { id: 0, start: 0, len: 1 },
+                FileSpan { id: 2, start: 1, len: 2 },
+                FileSpan { id: 1, start: 3, len: 3 },
+                FileSpan { id: 2, start: 6, len: 3 },
+            ],
+        );
+
+        let disk = DiskMap::from_unpacked(&unpack_spans(&"2333133121414131402".to_string())).unwrap();
+        assert_eq!(checksum_files(&compact_blocks(&disk)), 1928);
+    }
+
+    #[test]
+    fn test_compact_blocks_matches_block_vector() {
+        let mut rng = Lcg::new(2024);
+        for len in 1..60 {
+            let disk_map = random_disk_map(&mut rng, len);
+            let unpacked_fs = unpack_filesystem(&disk_map);
+            if unpacked_fs.is_empty() {
+                continue;
+            }
+            let disk = DiskMap::from_unpacked(&unpack_spans(&disk_map)).unwrap();
+            assert_eq!(
+                checksum_files(&compact_blocks(&disk)),
+                calculate_checksum(&defrag_filesystem(&unpacked_fs)) as u128,
+                "{}",
+                disk_map,
+            );
+        }
+    }
+
+    #[test]
+    fn test_compact_blocks_on_huge_disk() {
+        let disk = DiskMap::from_unpacked(&[
+            (1, Some(0)),
+            (3_000_000_000, None),
+            (4_000_000_000, Some(1)),
+        ]).unwrap();
+        let files = compact_blocks(&disk);
+        assert_eq!(files.len(), 3);
+        // The moved blocks and the ones left in place form one run from 1.
+        let len = 4_000_000_000u128;
+        assert_eq!(checksum_files(&files), len * (len + 1) / 2);
+    }
 }
 
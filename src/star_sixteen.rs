@@ -1,5 +1,15 @@
-use std::{collections::HashMap, fs};
-use regex::Regex;
+use std::{collections::HashSet, fs};
+
+use crate::star_fifteen::{
+    find_antinodes,
+    group_antinodes,
+    parse_input,
+    parse_spacing,
+    print_report,
+    Antenna,
+    Antinode,
+    Spacing,
+};
 
 pub fn run() {
     let file = fs::read_to_string("./inputs/star_sixteen.txt").unwrap();
@@ -9,151 +19,157 @@ pub fn run() {
     println!("Result: {}", result);
 }
 
-#[derive(Clone, Copy, PartialEq, Debug, Hash, Eq)]
-struct Antenna {
-    frequency: char,
-    x: isize,
-    y: isize,
-}
-
-#[derive(Clone, Copy, PartialEq, Debug, Hash, Eq)]
-struct Antinode {
-    x: isize,
-    y: isize,
+pub fn run_report(plain: bool) {
+    let file = fs::read_to_string("./inputs/star_sixteen.txt").unwrap();
+    let (antennas, w, h) = parse_input(&file);
+    print_report(&antennas, Spacing::Harmonics, w, h, !plain);
 }
 
-fn parse_input(input: &String) -> (Vec<Antenna>, usize, usize) {
-    let w = input.lines().next().unwrap().len();
-    let h = input.lines().count();
+pub fn run_lattice() {
+    let file = fs::read_to_string("./inputs/star_sixteen.txt").unwrap();
+    let (antennas, w, h) = parse_input(&file);
 
-    let re = Regex::new(r"[0-9a-zA-Z]").unwrap();
+    println!("Pairwise steps: {}", find_antinodes(&antennas, Spacing::Harmonics, w, h).len());
+    println!("Every lattice point: {}", find_antinodes(&antennas, Spacing::Lattice, w, h).len());
+}
 
-    let mut input = input.clone();
-    input.retain(|s| !s.is_whitespace());
+pub fn run_optimize(frequency: char, budget: usize, goal: &str, spacing: &str, plain: bool) {
+    let goal = match goal {
+        "max" => Goal::Maximize,
+        "min" => Goal::Minimize,
+        _ => {
+            eprintln!("Unknown goal {:?}, expected max or min", goal);
+            return;
+        },
+    };
+    let Some(spacing) = parse_spacing(spacing) else {
+        eprintln!("Unknown spacing {:?}, expected a ratio like 2:1, harmonics or lattice", spacing);
+        return;
+    };
+    let file = fs::read_to_string("./inputs/star_sixteen.txt").unwrap();
+    let (antennas, w, h) = parse_input(&file);
 
-    let mut antennas = vec![];
+    let before = find_antinodes(&antennas, spacing, w, h).len();
+    let placement = optimize_placement(&antennas, frequency, budget, spacing, goal, w, h);
+    let mut all_antennas = antennas.clone();
+    all_antennas.extend(&placement.antennas);
 
-    let mut x = 0;
-    let mut y = 0;
-    for found_char in input.chars() {
-        if re.is_match(&found_char.to_string()) {
-            antennas.push(Antenna{frequency: found_char, x: x as isize, y: y as isize})
-        }
-        x += 1;
-        if x >= w {
-            x = 0;
-            y += 1;
-        }
+    print_report(&all_antennas, spacing, w, h, !plain);
+    for antenna in &placement.antennas {
+        println!("Place {} at {},{}", antenna.frequency, antenna.x, antenna.y);
     }
+    println!("Antinodes: {} -> {}", before, placement.antinodes);
+}
 
-    (antennas, w, h)
+fn count_antinodes(antennas: &[Antenna], w: usize, h: usize) -> usize {
+    find_antinodes(antennas, Spacing::Harmonics, w, h).len()
 }
 
-fn create_antinodes(antenna: &Antenna, target_antenna: &Antenna, w: usize, h: usize) -> Vec<Antinode> {
-    if antenna.frequency != target_antenna.frequency {
-        return vec![];
-    }
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Goal {
+    Maximize,
+    Minimize,
+}
 
-    if antenna.y > target_antenna.y {
-        return create_antinodes(target_antenna, antenna, w, h);
-    }
+#[derive(PartialEq, Debug)]
+pub(crate) struct Placement {
+    pub antennas: Vec<Antenna>,
+    pub antinodes: usize,
+}
 
-    let point_x = (target_antenna.x as f64 - antenna.x as f64).powf(2.0);
-    let point_y = (target_antenna.y as f64 - antenna.y as f64).powf(2.0);
+// Only the chosen frequency's antinodes change while placing, so the other
+// frequencies are worked out once and every candidate is scored against them
+struct PlacementScorer {
+    others: HashSet<Antinode>,
+    group: Vec<Antenna>,
+    spacing: Spacing,
+    w: usize,
+    h: usize,
+}
 
-    let distance = (point_x + point_y * 1.0).sqrt();
-    if distance.floor() < 1.0 {
-        return vec![];
+impl PlacementScorer {
+    fn score(&self, placed: &[Antenna]) -> usize {
+        let mut group = self.group.clone();
+        group.extend(placed);
+        let antinodes = group_antinodes(&group, self.spacing, self.w, self.h);
+        self.others.len() + antinodes.difference(&self.others).count()
     }
+}
 
-    let vector = Antinode{
-        x: antenna.x - target_antenna.x,
-        y: target_antenna.y - antenna.y,
+// Places the antennas one at a time on whichever free cell scores best, then
+// keeps moving single antennas to other free cells while that still helps.
+// Ties go to the first cell in reading order, so results are repeatable.
+pub(crate) fn optimize_placement(
+    antennas: &[Antenna],
+    frequency: char,
+    budget: usize,
+    spacing: Spacing,
+    goal: Goal,
+    w: usize,
+    h: usize,
+) -> Placement {
+    let (group, others): (Vec<Antenna>, Vec<Antenna>) = antennas.iter().partition(|antenna| antenna.frequency == frequency);
+    let scorer = PlacementScorer { others: find_antinodes(&others, spacing, w, h), group, spacing, w, h };
+    let is_better = |score: usize, best: usize| match goal {
+        Goal::Maximize => score > best,
+        Goal::Minimize => score < best,
     };
 
-    let mut generated_antinodes = vec![];
-    generated_antinodes.push(Antinode { x: antenna.x, y: antenna.y });
-    generated_antinodes.push(Antinode { x: target_antenna.x, y: target_antenna.y });
-
-    // generate bottom to top
-    let mut is_valid = true;
-    let mut last_antinode_x = antenna.x;
-    let mut last_antinode_y = antenna.y;
-    while is_valid {
-        let antinode = Antinode{
-            x: last_antinode_x + vector.x,
-            y: last_antinode_y - vector.y,
-        };
-        is_valid = antinode.x >= 0 && antinode.x < w as isize &&
-            antinode.y >= 0 && antinode.y < h as isize;
-
-        if is_valid {
-            generated_antinodes.push(antinode);
-            last_antinode_x = antinode.x;
-            last_antinode_y = antinode.y;
+    let occupied: HashSet<(isize, isize)> = antennas.iter().map(|antenna| (antenna.x, antenna.y)).collect();
+    let cells: Vec<Antenna> = (0..h as isize)
+        .flat_map(|y| (0..w as isize).map(move |x| Antenna { frequency, x, y }))
+        .filter(|antenna| !occupied.contains(&(antenna.x, antenna.y)))
+        .collect();
+
+    let mut placed: Vec<Antenna> = vec![];
+    for _ in 0..budget {
+        let mut best: Option<(usize, Antenna)> = None;
+        for cell in &cells {
+            if placed.contains(cell) {
+                continue;
+            }
+            placed.push(*cell);
+            let score = scorer.score(&placed);
+            placed.pop();
+            if best.is_none_or(|(best_score, _)| is_better(score, best_score)) {
+                best = Some((score, *cell));
+            }
         }
-    }
-
-    // generate top to bottom
-    let mut is_valid = true;
-    let mut last_antinode_x = target_antenna.x;
-    let mut last_antinode_y = target_antenna.y;
-    while is_valid {
-        let antinode = Antinode{
-            x: last_antinode_x + (vector.x * -1),
-            y: last_antinode_y + vector.y,
+        let Some((_, cell)) = best else {
+            break;
         };
-        is_valid = antinode.x >= 0 && antinode.x < w as isize &&
-            antinode.y >= 0 && antinode.y < h as isize;
-
-        if is_valid {
-            generated_antinodes.push(antinode);
-            last_antinode_x = antinode.x;
-            last_antinode_y = antinode.y;
-        }
+        placed.push(cell);
     }
 
-    generated_antinodes
-}
-
-fn count_antinodes(antennas: &Vec<Antenna>, w: usize, h: usize) -> usize {
-    let mut all_antinodes = vec![];
-    for antenna in antennas {
-        for target_antenna in antennas {
-            let antinodes = create_antinodes(antenna, target_antenna, w, h);
-            all_antinodes.extend(antinodes);
-        }
-    }
-
-    print_antinodes(&all_antinodes, w, h);
-
-    let mut antinodes_map = HashMap::new();
-    for antinode in all_antinodes {
-        antinodes_map.insert(antinode, true);
-    }
-
-    antinodes_map.values().count()
-}
-
-fn print_antinodes(antinodes: &Vec<Antinode>, w: usize, h: usize) {
-    for y in 0..h {
-        for x in 0..w {
-            let mut char = '.';
-            for antinode in antinodes {
-                if antinode.x == x as isize && antinode.y == y as isize {
-                    char = '#';
+    let mut antinodes = scorer.score(&placed);
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for index in 0..placed.len() {
+            let current = placed[index];
+            for cell in &cells {
+                if placed.contains(cell) {
+                    continue;
                 }
+                placed[index] = *cell;
+                let score = scorer.score(&placed);
+                if is_better(score, antinodes) {
+                    antinodes = score;
+                    improved = true;
+                    break;
+                }
+                placed[index] = current;
             }
-            print!("{}", char);
         }
-        print!("\n");
     }
-}
 
+    Placement { antennas: placed, antinodes }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::star_fifteen::{create_antinodes, Antinode};
 
     #[test]
     fn test_create_antinodes() {
@@ -161,7 +177,7 @@ mod tests {
         let antenna_b = Antenna{frequency: 'T', x: 3, y: 1};
 
         assert_eq!(
-            create_antinodes(&antenna_a, &antenna_b, 10, 10),
+            create_antinodes(&antenna_a, &antenna_b, Spacing::Harmonics, 10, 10),
             vec![Antinode{x: 0, y: 0}, Antinode{x: 3, y: 1}, Antinode{x: 6, y: 2}, Antinode{x: 9, y: 3}],
         );
 
@@ -169,7 +185,7 @@ mod tests {
         let antenna_b = Antenna{frequency: 'T', x: 1, y: 2};
 
         assert_eq!(
-            create_antinodes(&antenna_a, &antenna_b, 10, 10),
+            create_antinodes(&antenna_a, &antenna_b, Spacing::Harmonics, 10, 10),
             vec![Antinode{x: 3, y: 1}, Antinode{x: 1, y: 2}, Antinode{x: 5, y: 0}],
         );
     }
@@ -195,6 +211,123 @@ mod tests {
         assert_eq!(count_antinodes(&antennas, 10, 10), 9);
     }
 
+    #[test]
+    fn test_create_antinodes_on_every_lattice_point() {
+        let antenna_a = Antenna{frequency: 'T', x: 1, y: 0};
+        let antenna_b = Antenna{frequency: 'T', x: 3, y: 4};
+
+        assert_eq!(
+            create_antinodes(&antenna_a, &antenna_b, Spacing::Harmonics, 10, 10),
+            vec![Antinode{x: 1, y: 0}, Antinode{x: 3, y: 4}, Antinode{x: 5, y: 8}],
+        );
+        assert_eq!(
+            create_antinodes(&antenna_a, &antenna_b, Spacing::Lattice, 10, 10),
+            vec![Antinode{x: 1, y: 0}, Antinode{x: 2, y: 2}, Antinode{x: 3, y: 4}, Antinode{x: 4, y: 6}, Antinode{x: 5, y: 8}],
+        );
+    }
+
+    #[test]
+    fn test_lattice_and_pairwise_steps_on_maps() {
+        let count = |map: &[&str], spacing| {
+            let (antennas, w, h) = parse_input(&map.join("\n"));
+            find_antinodes(&antennas, spacing, w, h).len()
+        };
+
+        // Every difference is already reduced, so both agree
+        let map = [
+            "T.........",
+            "...T......",
+            ".T........",
+            "..........",
+            "..........",
+            "..........",
+            "..........",
+            "..........",
+            "..........",
+            "..........",
+        ];
+        assert_eq!(count(&map, Spacing::Harmonics), 9);
+        assert_eq!(count(&map, Spacing::Lattice), 9);
+
+        // The a pair is (2,4) apart and the b pair (3,-3), so pairwise steps
+        // skip every point in between
+        let map = [
+            ".a.......",
+            ".........",
+            ".........",
+            ".........",
+            "...a.....",
+            "......b..",
+            ".........",
+            ".........",
+            "...b.....",
+        ];
+        assert_eq!(count(&map, Spacing::Harmonics), 5);
+        assert_eq!(count(&map, Spacing::Lattice), 11);
+    }
+
+    #[test]
+    fn test_optimize_placement() {
+        let antennas = [Antenna{frequency: 'a', x: 2, y: 2}];
+        let ratio = Spacing::Ratio { far: 2, near: 1 };
+
+        // A neighbour keeps both antinodes on a 5x5 map, and nothing can push
+        // the one on the far side of (2,2) off it
+        assert_eq!(
+            optimize_placement(&antennas, 'a', 1, ratio, Goal::Maximize, 5, 5),
+            Placement { antennas: vec![Antenna{frequency: 'a', x: 1, y: 1}], antinodes: 2 },
+        );
+        assert_eq!(
+            optimize_placement(&antennas, 'a', 1, ratio, Goal::Minimize, 5, 5),
+            Placement { antennas: vec![Antenna{frequency: 'a', x: 0, y: 0}], antinodes: 1 },
+        );
+        // A new frequency needs two antennas before it has any antinodes
+        let placement = optimize_placement(&antennas, 'b', 1, ratio, Goal::Maximize, 5, 5);
+        assert_eq!(placement.antinodes, 0);
+        // Nowhere left to place anything
+        let antennas = [Antenna{frequency: 'a', x: 0, y: 0}];
+        let placement = optimize_placement(&antennas, 'a', 3, ratio, Goal::Maximize, 1, 1);
+        assert_eq!(placement, Placement { antennas: vec![], antinodes: 0 });
+    }
+
+    #[test]
+    fn test_optimize_placement_is_a_local_optimum() {
+        let map = [
+            "......",
+            ".a..b.",
+            "......",
+            "...a..",
+            "......",
+            "....b.",
+        ];
+        let (antennas, w, h) = parse_input(&map.join("\n"));
+
+        for (goal, spacing) in [(Goal::Maximize, Spacing::Harmonics), (Goal::Minimize, Spacing::Lattice)] {
+            let placement = optimize_placement(&antennas, 'a', 2, spacing, goal, w, h);
+            let mut all_antennas = antennas.clone();
+            all_antennas.extend(&placement.antennas);
+            assert_eq!(find_antinodes(&all_antennas, spacing, w, h).len(), placement.antinodes);
+
+            let occupied: HashSet<(isize, isize)> = all_antennas.iter().map(|antenna| (antenna.x, antenna.y)).collect();
+            assert_eq!(occupied.len(), all_antennas.len());
+
+            for index in 0..placement.antennas.len() {
+                for (x, y) in (0..h as isize).flat_map(|y| (0..w as isize).map(move |x| (x, y))) {
+                    if occupied.contains(&(x, y)) {
+                        continue;
+                    }
+                    let mut moved = all_antennas.clone();
+                    moved[antennas.len() + index] = Antenna{frequency: 'a', x, y};
+                    let count = find_antinodes(&moved, spacing, w, h).len();
+                    match goal {
+                        Goal::Maximize => assert!(count <= placement.antinodes),
+                        Goal::Minimize => assert!(count >= placement.antinodes),
+                    }
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_parse_input() {
         let input = vec![
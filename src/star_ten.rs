@@ -1,21 +1,94 @@
-use std::fs;
+use std::{collections::{BTreeSet, HashSet}, fmt, fs, time::Instant};
 use regex::Regex;
 
 pub fn run() {
     let file = fs::read_to_string("./inputs/star_ten.txt").unwrap();
     let input = file.lines();
     let (rules, updates) = parse_input(input);
-    let invalid_updates = get_invalid_updates(&rules, &updates);
+    let graph = RuleGraph::from_rules(&rules);
+    let invalid_updates = get_invalid_updates(&graph, &updates);
     let mut sorted_invalid_updates = vec![];
     for update in invalid_updates {
-        let necessary_rules = find_necessary_rules(&rules, &update);
-        let new_sorted_update = sort_invalid_update(&necessary_rules, &update);
-        sorted_invalid_updates.push(new_sorted_update.clone());
+        match sort_invalid_update(&graph, &update) {
+            Ok(sorted_update) => sorted_invalid_updates.push(sorted_update),
+            Err(err) => eprintln!("Skipping update {:?}: {}", update, err),
+        }
     }
     let result = sum_updates_mid_numbers(&sorted_invalid_updates);
     println!("Result: {}", result);
 }
 
+pub fn run_bench() {
+    let (rules, updates) = generate_input(5_000, 50_000, 2_000, 25, 2024);
+
+    let start = Instant::now();
+    let by_scan = updates.iter().filter(|update| !is_update_valid_by_scan(&rules, update)).count();
+    let scan_time = start.elapsed();
+
+    let start = Instant::now();
+    let graph = RuleGraph::from_rules(&rules);
+    let invalid_updates = get_invalid_updates(&graph, &updates);
+    let lookup_time = start.elapsed();
+    assert_eq!(by_scan, invalid_updates.len());
+
+    let start = Instant::now();
+    let sorted_updates: Vec<Vec<isize>> = invalid_updates.iter()
+        .map(|update| sort_invalid_update(&graph, update).unwrap())
+        .collect();
+    let result = sum_updates_mid_numbers(&sorted_updates);
+    let sort_time = start.elapsed();
+
+    println!("{} rules, {} updates, {} invalid", rules.len(), updates.len(), by_scan);
+    println!("Validation by scan:   {:?}", scan_time);
+    println!("Validation by lookup: {:?}", lookup_time);
+    println!("Sorting by lookup:    {:?}", sort_time);
+    println!("Result: {}", result);
+}
+
+// Seeded generator for benchmark inputs and randomized tests, so runs are
+// reproducible without pulling in a random number crate.
+pub(crate) struct Lcg(u64);
+
+impl Lcg {
+    pub(crate) fn new(seed: u64) -> Self {
+        Lcg(seed)
+    }
+
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 33) % bound
+    }
+}
+
+// Rules always point from a lower page to a higher one so the generated
+// input never contains a cycle.
+fn generate_input(pages: u64, rule_count: usize, update_count: usize, update_len: usize, seed: u64) -> (Vec<(isize, isize)>, Vec<Vec<isize>>) {
+    let mut rng = Lcg::new(seed);
+    let mut next_page = || rng.below(pages) as isize;
+
+    let mut rules = vec![];
+    while rules.len() < rule_count {
+        let (page_x, page_y) = (next_page(), next_page());
+        if page_x != page_y {
+            rules.push((page_x.min(page_y), page_x.max(page_y)));
+        }
+    }
+
+    let mut updates = vec![];
+    for _ in 0..update_count {
+        let mut update = vec![];
+        while update.len() < update_len {
+            let page = next_page();
+            if !update.contains(&page) {
+                update.push(page);
+            }
+        }
+        updates.push(update);
+    }
+
+    (rules, updates)
+}
+
 fn parse_input<'a, I>(str_lines: I) -> (Vec<(isize, isize)>, Vec<Vec<isize>>)
 where
     I: IntoIterator<Item = &'a str>
@@ -47,11 +120,11 @@ fn sum_updates_mid_numbers(invalid_updates: &Vec<Vec<isize>>) -> isize {
     result
 }
 
-fn get_invalid_updates(rules: &Vec<(isize, isize)>, updates: &Vec<Vec<isize>>) -> Vec<Vec<isize>> {
+fn get_invalid_updates(graph: &RuleGraph, updates: &Vec<Vec<isize>>) -> Vec<Vec<isize>> {
     let mut valid_updates = vec![];
 
     for update in updates {
-        if !is_update_valid(&rules, update) {
+        if !graph.is_update_valid(update) {
             valid_updates.push(update.clone());
         }
     }
@@ -59,55 +132,97 @@ fn get_invalid_updates(rules: &Vec<(isize, isize)>, updates: &Vec<Vec<isize>>) -
     valid_updates
 }
 
-fn sort_invalid_update(rules: &Vec<(isize, isize)>, update: &Vec<isize>) -> Vec<isize> {
-    let mut sorted_update = update.clone();
-    let mut is_valid = false;
-
-    while !is_valid {
-        for (rule_x, rule_y) in rules {
-            let mut found_x_index = None;
-            let mut found_y_index = None;
-            for (index, page) in sorted_update.iter().enumerate() {
-                if found_x_index.is_none() && *page == *rule_x {
-                    found_x_index = Some(index)
-                }
-                if found_y_index.is_none() && *page == *rule_y {
-                    found_y_index = Some(index)
-                }
+#[derive(PartialEq, Debug)]
+pub(crate) struct CycleError {
+    pub pages: Vec<isize>,
+}
+
+impl fmt::Display for CycleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pages: Vec<String> = self.pages.iter().map(|page| page.to_string()).collect();
+        write!(f, "rules contain a cycle: {} -> {}", pages.join(" -> "), pages[0])
+    }
+}
+
+// Rules are stored as a set of (before, after) edges, so checking a pair of
+// pages costs the same no matter how many rules there are.
+#[derive(Debug, Default)]
+pub(crate) struct RuleGraph {
+    edges: HashSet<(isize, isize)>,
+}
 
-                if found_x_index.is_some() && found_y_index.is_some() {
-                    let x_index = found_x_index.unwrap();
-                    let y_index = found_y_index.unwrap();
+impl RuleGraph {
+    pub fn from_rules(rules: &[(isize, isize)]) -> Self {
+        Self { edges: rules.iter().copied().collect() }
+    }
+
+    pub fn must_precede(&self, page_x: isize, page_y: isize) -> bool {
+        self.edges.contains(&(page_x, page_y))
+    }
 
-                    if x_index > y_index {
-                        let x_value = *sorted_update.get(x_index).unwrap();
-                        sorted_update.remove(x_index);
-                        sorted_update.insert(y_index, x_value);
-                    }
-                    break;
+    pub fn is_update_valid(&self, update: &[isize]) -> bool {
+        for (i, page) in update.iter().enumerate() {
+            for later_page in &update[i + 1..] {
+                if self.must_precede(*later_page, *page) {
+                    return false;
                 }
             }
         }
-
-        is_valid = is_update_valid(rules, &sorted_update);
+        true
     }
-
-    sorted_update
 }
 
-fn find_necessary_rules(rules: &Vec<(isize, isize)>, update: &Vec<isize>) -> Vec<(isize, isize)> {
-    let mut necessary_rules = vec![];
-    for (rule_x, rule_y) in rules {
-        if !update.contains(rule_x) || !update.contains(rule_y) {
-            continue;
+// Kahn's algorithm over the subgraph induced by the update's pages.
+// Ready pages are taken in their original order so already sorted
+// stretches of the update are left untouched.
+pub(crate) fn sort_invalid_update(graph: &RuleGraph, update: &[isize]) -> Result<Vec<isize>, CycleError> {
+    let mut successors = vec![vec![]; update.len()];
+    let mut predecessors = vec![vec![]; update.len()];
+    let mut in_degree = vec![0; update.len()];
+    for (from, page_x) in update.iter().enumerate() {
+        for (to, page_y) in update.iter().enumerate() {
+            if graph.must_precede(*page_x, *page_y) {
+                successors[from].push(to);
+                predecessors[to].push(from);
+                in_degree[to] += 1;
+            }
         }
-        necessary_rules.push((*rule_x, *rule_y));
     }
 
-    necessary_rules
+    let mut ready: BTreeSet<usize> = (0..update.len()).filter(|i| in_degree[*i] == 0).collect();
+    let mut sorted_update = vec![];
+    while let Some(index) = ready.pop_first() {
+        sorted_update.push(update[index]);
+        for next in &successors[index] {
+            in_degree[*next] -= 1;
+            if in_degree[*next] == 0 {
+                ready.insert(*next);
+            }
+        }
+    }
+
+    if sorted_update.len() == update.len() {
+        return Ok(sorted_update);
+    }
+
+    // Every page left over still has a left over predecessor, so walking
+    // backwards from any of them has to run into a cycle.
+    let mut index = (0..update.len()).find(|i| in_degree[*i] > 0).unwrap();
+    let mut seen = vec![];
+    while !seen.contains(&index) {
+        seen.push(index);
+        index = *predecessors[index].iter().find(|i| in_degree[**i] > 0).unwrap();
+    }
+    let mut pages: Vec<isize> = seen[seen.iter().position(|i| *i == index).unwrap()..]
+        .iter()
+        .map(|i| update[*i])
+        .collect();
+    pages.reverse();
+
+    Err(CycleError { pages })
 }
 
-fn is_update_valid(rules: &Vec<(isize, isize)>, update: &Vec<isize>) -> bool {
+fn is_update_valid_by_scan(rules: &Vec<(isize, isize)>, update: &Vec<isize>) -> bool {
     for (rule_x, rule_y) in rules {
         let mut found_x_index = None;
         let mut found_y_index = None;
@@ -257,12 +372,34 @@ mod tests {
             (75, 13),
             (53, 13),
         ];
-        assert!(is_update_valid(&rules, &vec![75,47,61,53,29]));
-        assert!(is_update_valid(&rules, &vec![97,61,53,29,13]));
-        assert!(is_update_valid(&rules, &vec![75,29,13]));
-        assert!(!is_update_valid(&rules, &vec![75,97,47,61,53]));
-        assert!(!is_update_valid(&rules, &vec![61,13,29]));
-        assert!(!is_update_valid(&rules, &vec![97,13,75,29,47]));
+        assert!(is_update_valid_by_scan(&rules, &vec![75,47,61,53,29]));
+        assert!(is_update_valid_by_scan(&rules, &vec![97,61,53,29,13]));
+        assert!(is_update_valid_by_scan(&rules, &vec![75,29,13]));
+        assert!(!is_update_valid_by_scan(&rules, &vec![75,97,47,61,53]));
+        assert!(!is_update_valid_by_scan(&rules, &vec![61,13,29]));
+        assert!(!is_update_valid_by_scan(&rules, &vec![97,13,75,29,47]));
+
+        let graph = RuleGraph::from_rules(&rules);
+        assert!(graph.is_update_valid(&[75,47,61,53,29]));
+        assert!(graph.is_update_valid(&[97,61,53,29,13]));
+        assert!(graph.is_update_valid(&[75,29,13]));
+        assert!(!graph.is_update_valid(&[75,97,47,61,53]));
+        assert!(!graph.is_update_valid(&[61,13,29]));
+        assert!(!graph.is_update_valid(&[97,13,75,29,47]));
+    }
+
+    #[test]
+    fn test_generate_input() {
+        let (rules, updates) = generate_input(100, 1_000, 50, 10, 7);
+        assert_eq!(rules.len(), 1_000);
+        assert_eq!(updates.len(), 50);
+        let graph = RuleGraph::from_rules(&rules);
+        for update in &updates {
+            assert_eq!(update.len(), 10);
+            assert_eq!(graph.is_update_valid(update), is_update_valid_by_scan(&rules, update));
+            let sorted_update = sort_invalid_update(&graph, update).unwrap();
+            assert!(is_update_valid_by_scan(&rules, &sorted_update));
+        }
     }
 
     #[test]
@@ -290,9 +427,31 @@ mod tests {
             (75, 13),
             (53, 13),
         ];
-        assert_eq!(sort_invalid_update(&rules, &vec![75,97,47,61,53]), vec![97,75,47,61,53]);
-        assert_eq!(sort_invalid_update(&rules, &vec![61,13,29]), vec![61,29,13]);
-        assert_eq!(sort_invalid_update(&rules, &vec![97,13,75,29,47]), vec![97,75,47,29,13]);
+        let graph = RuleGraph::from_rules(&rules);
+        assert_eq!(sort_invalid_update(&graph, &[75,97,47,61,53]), Ok(vec![97,75,47,61,53]));
+        assert_eq!(sort_invalid_update(&graph, &[61,13,29]), Ok(vec![61,29,13]));
+        assert_eq!(sort_invalid_update(&graph, &[97,13,75,29,47]), Ok(vec![97,75,47,29,13]));
+        assert_eq!(sort_invalid_update(&graph, &[75,47,61,53,29]), Ok(vec![75,47,61,53,29]));
+    }
+
+    #[test]
+    fn test_sort_invalid_update_cycle() {
+        let graph = RuleGraph::from_rules(&[
+            (10, 20),
+            (20, 30),
+            (30, 10),
+            (30, 40),
+            (50, 10),
+        ]);
+        let err = sort_invalid_update(&graph, &[40, 30, 50, 20, 10]).unwrap_err();
+        let mut pages = err.pages.clone();
+        pages.sort();
+        assert_eq!(pages, vec![10, 20, 30]);
+        assert_eq!(err.pages.len(), 3);
+        assert!(err.to_string().starts_with("rules contain a cycle: "));
+
+        // The cycle only matters when all of its pages are in the update
+        assert_eq!(sort_invalid_update(&graph, &[30, 10, 50]), Ok(vec![30, 50, 10]));
     }
 
     #[test]
@@ -329,7 +488,7 @@ mod tests {
             vec![97,13,75,29,47],
         ];
         assert_eq!(
-            get_invalid_updates(&rules, &updates),
+            get_invalid_updates(&RuleGraph::from_rules(&rules), &updates),
             vec![
                 vec![75,97,47,61,53],
                 vec![61,13,29],
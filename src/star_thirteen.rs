@@ -1,5 +1,7 @@
 use std::fs;
 
+use crate::star_fourteen::{can_solve, report_precedence, Mul, Number, Sum};
+
 pub fn run() {
     let file = fs::read_to_string("./inputs/star_thirteen.txt").unwrap();
     let result = process_input(file.lines());
@@ -7,7 +9,12 @@ pub fn run() {
     println!("Result: {}", result);
 }
 
-fn process_input<'a, I>(str_lines: I) -> isize 
+pub fn run_precedence() {
+    let file = fs::read_to_string("./inputs/star_thirteen.txt").unwrap();
+    report_precedence(&file, &[&Sum, &Mul]);
+}
+
+fn process_input<'a, I>(str_lines: I) -> Number 
 where
     I: IntoIterator<Item = &'a str>
 {
@@ -16,11 +23,11 @@ where
         .map(|(expected_result, operands)| compute_formula(expected_result, &operands))
         .filter(|result| result.is_some())
         .map(|result| result.unwrap())
-        .reduce(|acc, e| acc + e)
-        .unwrap_or(0)
+        .reduce(|acc, e| &acc + &e)
+        .unwrap_or(Number::Small(0))
 }
 
-fn parse_formula(str_formula: &String) -> (isize, Vec<isize>) {
+fn parse_formula(str_formula: &String) -> (Number, Vec<Number>) {
     let mut split = str_formula.split(":");
     let expected_result = split
         .next()
@@ -37,67 +44,22 @@ fn parse_formula(str_formula: &String) -> (isize, Vec<isize>) {
     (expected_result, nums)
 }
 
-#[derive(Copy, Clone)]
-enum Operator {
-    Mul,
-    Sum,
-}
-
-fn compute_formula(expected_result: isize, operands: &Vec<isize>) -> Option<isize> {
-    let mut current_mask: u16 = 0b00000000;
-    let max_attempts = 2_usize.pow((operands.len() as u32) - 1);
-
-    for _ in 0..max_attempts {
-        let mut result = 0;
-        let mut is_first = true;
-
-        let operators = mask_to_operators(current_mask, operands.len());
-
-        for (operand, operator) in operands.iter().zip(operators) {
-            if is_first {
-                result = *operand;
-                is_first = false;
-                continue;
-            }
-            match operator {
-                Operator::Mul => {result *= *operand},
-                Operator::Sum => {result += *operand},
-            };
-        }
-
-
-        if result == expected_result {
-            return Some(result);
-        }
-
-        current_mask += 1;
+fn compute_formula(expected_result: Number, operands: &Vec<Number>) -> Option<Number> {
+    if can_solve(&expected_result, operands, &[&Sum, &Mul]) {
+        return Some(expected_result);
     }
-
     None
 }
 
-fn mask_to_operators(mask: u16, count: usize) -> Vec<Operator> {
-    let mut operators = vec![];
-    operators.push(Operator::Sum); // push dummy operator just so that the len matches with operands
-    let mut mask_temp = mask;
-    for _ in 0..count {
-        let operator = match mask_temp & 1 {
-            0 => Operator::Sum,
-            1 => Operator::Mul,
-            _ => unreachable!(),
-        };
-        operators.push(operator);
-        mask_temp >>= 1;
-    }
-
-    operators
-}
-
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn numbers(values: &[u128]) -> Vec<Number> {
+        values.iter().map(|value| Number::from(*value)).collect()
+    }
+
     #[test]
     fn test_process_input() {
         let input = vec![
@@ -111,32 +73,32 @@ mod tests {
             "21037: 9 7 18 13",
             "292: 11 6 16 20",
         ];
-        assert_eq!(process_input(input), 3749);
+        assert_eq!(process_input(input), Number::from(3749));
     }
 
     #[test]
     fn test_compute_formula() {
-        assert_eq!(compute_formula(190, &vec![10, 19]), Some(190));
-        assert_eq!(compute_formula(3267, &vec![81, 40, 27]), Some(3267));
-        assert_eq!(compute_formula(83, &vec![17, 5]), None);
-        assert_eq!(compute_formula(156, &vec![15, 6]), None);
-        assert_eq!(compute_formula(7290, &vec![6, 8, 6, 15]), None);
-        assert_eq!(compute_formula(161011, &vec![16, 10, 13]), None);
-        assert_eq!(compute_formula(192, &vec![17, 8, 14]), None);
-        assert_eq!(compute_formula(21037, &vec![9, 7, 18, 13]), None);
-        assert_eq!(compute_formula(292, &vec![11, 6, 16, 20]), Some(292));
+        assert_eq!(compute_formula(Number::from(190), &numbers(&[10, 19])), Some(Number::from(190)));
+        assert_eq!(compute_formula(Number::from(3267), &numbers(&[81, 40, 27])), Some(Number::from(3267)));
+        assert_eq!(compute_formula(Number::from(83), &numbers(&[17, 5])), None);
+        assert_eq!(compute_formula(Number::from(156), &numbers(&[15, 6])), None);
+        assert_eq!(compute_formula(Number::from(7290), &numbers(&[6, 8, 6, 15])), None);
+        assert_eq!(compute_formula(Number::from(161011), &numbers(&[16, 10, 13])), None);
+        assert_eq!(compute_formula(Number::from(192), &numbers(&[17, 8, 14])), None);
+        assert_eq!(compute_formula(Number::from(21037), &numbers(&[9, 7, 18, 13])), None);
+        assert_eq!(compute_formula(Number::from(292), &numbers(&[11, 6, 16, 20])), Some(Number::from(292)));
     }
 
     #[test]
     fn test_parse_formula() {
-        assert_eq!(parse_formula(&"190: 10 19".to_string()), (190, vec![10, 19]));
-        assert_eq!(parse_formula(&"3267: 81 40 27".to_string()), (3267, vec![81, 40, 27]));
-        assert_eq!(parse_formula(&"83: 17 5".to_string()), (83, vec![17, 5]));
-        assert_eq!(parse_formula(&"156: 15 6".to_string()), (156, vec![15, 6]));
-        assert_eq!(parse_formula(&"7290: 6 8 6 15".to_string()), (7290, vec![6, 8, 6, 15]));
-        assert_eq!(parse_formula(&"161011: 16 10 13".to_string()), (161011, vec![16, 10, 13]));
-        assert_eq!(parse_formula(&"192: 17 8 14".to_string()), (192, vec![17, 8, 14]));
-        assert_eq!(parse_formula(&"21037: 9 7 18 13".to_string()), (21037, vec![9, 7, 18, 13]));
-        assert_eq!(parse_formula(&"292: 11 6 16 20".to_string()), (292, vec![11, 6, 16, 20]));
+        assert_eq!(parse_formula(&"190: 10 19".to_string()), (Number::from(190), numbers(&[10, 19])));
+        assert_eq!(parse_formula(&"3267: 81 40 27".to_string()), (Number::from(3267), numbers(&[81, 40, 27])));
+        assert_eq!(parse_formula(&"83: 17 5".to_string()), (Number::from(83), numbers(&[17, 5])));
+        assert_eq!(parse_formula(&"156: 15 6".to_string()), (Number::from(156), numbers(&[15, 6])));
+        assert_eq!(parse_formula(&"7290: 6 8 6 15".to_string()), (Number::from(7290), numbers(&[6, 8, 6, 15])));
+        assert_eq!(parse_formula(&"161011: 16 10 13".to_string()), (Number::from(161011), numbers(&[16, 10, 13])));
+        assert_eq!(parse_formula(&"192: 17 8 14".to_string()), (Number::from(192), numbers(&[17, 8, 14])));
+        assert_eq!(parse_formula(&"21037: 9 7 18 13".to_string()), (Number::from(21037), numbers(&[9, 7, 18, 13])));
+        assert_eq!(parse_formula(&"292: 11 6 16 20".to_string()), (Number::from(292), numbers(&[11, 6, 16, 20])));
     }
 }
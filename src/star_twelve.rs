@@ -1,178 +1,162 @@
-use std::{collections::HashMap, fs};
+use std::{collections::HashSet, fs, thread};
+
+use crate::star_eleven::{parse_input, patrol_states, Guard, GuardDirection, ObstacleIndex, Obstruction};
 
 pub fn run() {
     let file = fs::read_to_string("./inputs/star_twelve.txt").unwrap();
     let (guard, obstructions, w, h) = parse_input(&file);
-    let result = count_loops(&guard, &obstructions, w, h);
+    let result = count_loops(&guard, &ObstacleIndex::new(&obstructions, w, h));
     println!("Result: {}", result);
 }
 
-fn parse_input(input: &String) -> (Guard, Vec<Obstruction>, usize, usize) {
-
-    let w = input.lines().next().unwrap().len();
-    let h = input.lines().count();
-
-    let mut input = input.clone();
-    input.retain(|s| !s.is_whitespace());
-
-    let mut guard = Guard{x: 0, y: 0, direction: GuardDirection::Up};
-    let mut obstructions = vec![];
-
-    for y in 0..h {
-        for x in 0..w {
-            let char = input.chars().nth(x + (y * w)).unwrap().to_string();
-            if char == "#" {
-                obstructions.push(
-                    Obstruction{x, y}
-                );
-            }
-
-            if "^v<>".contains(&char) {
-                guard.x = x;
-                guard.y = y;
-
-                guard.direction = match char.chars().next().unwrap() {
-                    '^' => GuardDirection::Up,
-                    'v' => GuardDirection::Down,
-                    '<' => GuardDirection::Left,
-                    '>' => GuardDirection::Right,
-                    _ => unreachable!(),
-                }
-            }
-        }
+pub fn run_loops(loop_number: Option<&str>) {
+    let file = fs::read_to_string("./inputs/star_twelve.txt").unwrap();
+    let (guard, obstructions, w, h) = parse_input(&file);
+    let index = ObstacleIndex::new(&obstructions, w, h);
+    let loops = find_loop_obstructions(&guard, &index);
+    for (i, (_, obstruction)) in loops.iter().enumerate() {
+        println!("{}: obstruction at ({}, {})", i + 1, obstruction.x, obstruction.y);
     }
-
-    (guard, obstructions, w, h)
+    if let Some(loop_number) = loop_number {
+        let Some((loop_number, (state, obstruction))) = loop_number.parse::<usize>()
+            .ok()
+            .and_then(|number| Some((number, loops.get(number.checked_sub(1)?)?)))
+        else {
+            eprintln!("Invalid loop number {:?}, expected 1 to {}", loop_number, loops.len());
+            return;
+        };
+        let found_loop = trace_loop(state, *obstruction, &index);
+        println!();
+        println!("Loop {}: cycle of {} states", loop_number, found_loop.cycle.len());
+        println!("{}", render_loop(&guard, &index, &found_loop));
+    }
+    println!("Result: {}", loops.len());
 }
 
-fn count_loops(guard: &Guard, obstructions: &Vec<Obstruction>, w: usize, h: usize) -> usize {
-    let mut loop_count = 0;
+#[derive(PartialEq, Debug)]
+struct Loop {
+    obstruction: Obstruction,
+    cycle: Vec<Guard>,
+}
 
-    for y in 0..h {
-        for x in 0..w {
-            let mut virtual_guard = Guard{x: guard.x, y: guard.y, direction: guard.direction};
-            let mut visited_positions = HashMap::new();
-            let mut obstacle_encounters= HashMap::new();
-            let mut virtual_obstructions = obstructions.clone();
-            virtual_obstructions.push(Obstruction {x, y});
-            while !virtual_guard.can_escape(w, h) {
-                let pos = (virtual_guard.x, virtual_guard.y);
-                visited_positions.insert(pos, virtual_guard.direction);
+fn count_loops(guard: &Guard, index: &ObstacleIndex) -> usize {
+    find_loop_obstructions(guard, index).len()
+}
 
-                if virtual_guard.is_obstructed(&virtual_obstructions) {
-                    let obstacle_encounter = (
-                        virtual_guard.x,
-                        virtual_guard.y,
-                        virtual_guard.direction
-                    );
-                    match obstacle_encounters.get(&obstacle_encounter) {
-                        Some(_) => {
-                            loop_count += 1;
-                            break;
-                        },
-                        None => {
-                            obstacle_encounters.insert(obstacle_encounter, true)
-                        }
-                    };
-                    virtual_guard.rotate_right();
-                    continue;
+// Returns every obstruction that traps the guard, along with the state the
+// guard is in right before reaching it.
+fn find_loop_obstructions(guard: &Guard, index: &ObstacleIndex) -> Vec<(Guard, Obstruction)> {
+    let candidates = find_candidates(guard, index);
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = candidates.len().div_ceil(threads).max(1);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = candidates.chunks(chunk_size).map(|chunk| {
+            scope.spawn(move || {
+                let mut index = index.clone();
+                let mut loops = vec![];
+                for (state, obstruction) in chunk {
+                    index.insert(obstruction.x, obstruction.y);
+                    if is_loop(state, &index) {
+                        loops.push((*state, *obstruction));
+                    }
+                    index.remove(obstruction.x, obstruction.y);
                 }
+                loops
+            })
+        }).collect();
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    })
+}
 
-                virtual_guard.step();
-
-                visited_positions.insert((virtual_guard.x, virtual_guard.y), virtual_guard.direction);
-            }
-
+// An obstruction can only change the patrol if it sits on the original
+// route, and the guard walks that route unchanged until it first reaches
+// it, so each simulation can start from the state right before that cell.
+fn find_candidates(guard: &Guard, index: &ObstacleIndex) -> Vec<(Guard, Obstruction)> {
+    let mut candidates = vec![];
+    let mut seen = vec![false; index.w * index.h];
+    seen[guard.x + guard.y * index.w] = true;
+
+    for states in patrol_states(guard, index).windows(2) {
+        let (state, next) = (states[0], states[1]);
+        if !seen[next.x + next.y * index.w] {
+            seen[next.x + next.y * index.w] = true;
+            candidates.push((state, Obstruction{x: next.x, y: next.y}));
         }
     }
 
-    loop_count
+    candidates
 }
 
+fn is_loop(guard: &Guard, index: &ObstacleIndex) -> bool {
+    let mut virtual_guard = *guard;
+    let mut obstacle_encounters = HashSet::new();
 
-#[derive(PartialEq, Debug, Copy, Clone)]
-struct Obstruction {
-    x: usize,
-    y: usize,
-}
+    while index.jump(&mut virtual_guard).is_some() {
+        if !obstacle_encounters.insert(virtual_guard) {
+            return true;
+        }
+        virtual_guard.rotate_right();
+    }
 
-#[derive(PartialEq, Debug, Copy, Clone, Eq, Hash)]
-enum GuardDirection {
-    Up,
-    Down,
-    Left,
-    Right,
+    false
 }
 
-#[derive(PartialEq, Debug)]
-struct Guard {
-    x: usize,
-    y: usize,
-    direction: GuardDirection,
+fn trace_loop(state: &Guard, obstruction: Obstruction, index: &ObstacleIndex) -> Loop {
+    let mut index = index.clone();
+    index.insert(obstruction.x, obstruction.y);
+    Loop { obstruction, cycle: find_cycle(state, &index) }
 }
 
-impl Guard {
-    pub fn can_escape(&self, w: usize, h: usize) -> bool {
-        if self.direction == GuardDirection::Left && self.x == 0 {
-            return true;
-        }
-        if self.direction == GuardDirection::Up && self.y == 0 {
-            return true;
-        }
-        if self.direction == GuardDirection::Right && self.x == w.saturating_sub(1) {
-            return true;
-        }
-        if self.direction == GuardDirection::Down && self.y == h.saturating_sub(1) {
-            return true;
-        }
-        false
-    }
+// The patrol stops at the first repeated obstacle encounter, so the states
+// from that encounter's first appearance onwards make up the cycle.
+fn find_cycle(guard: &Guard, index: &ObstacleIndex) -> Vec<Guard> {
+    let mut states = patrol_states(guard, index);
+    let repeated = states.pop().unwrap();
+    let start = states.iter().position(|state| *state == repeated).unwrap();
+    states.split_off(start)
+}
 
-    pub fn rotate_right(&mut self) {
-        self.direction = match self.direction {
-            GuardDirection::Up => GuardDirection::Right,
-            GuardDirection::Right => GuardDirection::Down,
-            GuardDirection::Down => GuardDirection::Left,
-            GuardDirection::Left => GuardDirection::Up,
+fn render_loop(guard: &Guard, index: &ObstacleIndex, found_loop: &Loop) -> String {
+    let mut vertical = vec![false; index.w * index.h];
+    let mut horizontal = vec![false; index.w * index.h];
+    for state in &found_loop.cycle {
+        match state.direction {
+            GuardDirection::Up | GuardDirection::Down => vertical[state.x + state.y * index.w] = true,
+            GuardDirection::Left | GuardDirection::Right => horizontal[state.x + state.y * index.w] = true,
         }
     }
 
-    pub fn step(&mut self) {
-        match self.direction {
-            GuardDirection::Up => {
-                if self.y > 0 {
-                    self.y -= 1;
+    let mut lines = vec![];
+    for y in 0..index.h {
+        let mut line = String::new();
+        for x in 0..index.w {
+            let char = if (x, y) == (found_loop.obstruction.x, found_loop.obstruction.y) {
+                'O'
+            } else if index.contains(x, y) {
+                '#'
+            } else if (x, y) == (guard.x, guard.y) {
+                guard.direction.glyph()
+            } else {
+                match (vertical[x + y * index.w], horizontal[x + y * index.w]) {
+                    (true, true) => '+',
+                    (true, false) => '|',
+                    (false, true) => '-',
+                    (false, false) => '.',
                 }
-            },
-            GuardDirection::Right => {
-                self.x += 1;
-            },
-            GuardDirection::Down => {
-                self.y += 1;
-            },
-            GuardDirection::Left => {
-                self.x -= 1;
-            },
-        };
-    }
-
-    pub fn is_obstructed(&self, obstructions: &Vec<Obstruction>) -> bool {
-        let mut virtual_guard = Guard {x: self.x, y: self.y, direction: self.direction};
-        virtual_guard.step();
-        for obstruction in obstructions {
-            if obstruction.x == virtual_guard.x && obstruction.y == virtual_guard.y {
-                return true;
-            }
+            };
+            line.push(char);
         }
-
-        false
+        lines.push(line);
     }
+    lines.join("\n")
 }
 
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::star_ten::Lcg;
 
     #[test]
     fn test_parse_input() {
@@ -221,114 +205,117 @@ mod tests {
             "#.........",
             "......#...",
         ].join("\n");
-        let (guard, obstructions, _, _) = parse_input(&input);
-        assert_eq!(count_loops(&guard, &obstructions, 10, 10), 6);
+        let (guard, obstructions, w, h) = parse_input(&input);
+        assert_eq!(count_loops(&guard, &ObstacleIndex::new(&obstructions, w, h)), 6);
     }
 
     #[test]
-    fn test_can_guard_escape() {
-        let guard = Guard{x: 0, y: 0, direction: GuardDirection::Left};
-        assert!(guard.can_escape(10, 10));
-        let guard = Guard{x: 0, y: 0, direction: GuardDirection::Up};
-        assert!(guard.can_escape(10, 10));
-        let guard = Guard{x: 9, y: 0, direction: GuardDirection::Right};
-        assert!(guard.can_escape(10, 10));
-        let guard = Guard{x: 9, y: 9, direction: GuardDirection::Down};
-        assert!(guard.can_escape(10, 10));
-
-        let guard = Guard{x: 5, y: 5, direction: GuardDirection::Left};
-        assert!(!guard.can_escape(10, 10));
-        let guard = Guard{x: 5, y: 5, direction: GuardDirection::Up};
-        assert!(!guard.can_escape(10, 10));
-        let guard = Guard{x: 5, y: 5, direction: GuardDirection::Right};
-        assert!(!guard.can_escape(10, 10));
-        let guard = Guard{x: 5, y: 5, direction: GuardDirection::Down};
-        assert!(!guard.can_escape(10, 10));
+    fn test_is_loop() {
+        let input = [
+            "....#.....",
+            ".........#",
+            "..........",
+            "..#.......",
+            ".......#..",
+            "..........",
+            ".#..^.....",
+            "........#.",
+            "#.........",
+            "......#...",
+        ].join("\n");
+        let (guard, obstructions, w, h) = parse_input(&input);
+        let mut index = ObstacleIndex::new(&obstructions, w, h);
+        assert!(!is_loop(&guard, &index));
+        index.insert(3, 6);
+        assert!(is_loop(&guard, &index));
     }
 
     #[test]
-    fn test_guard_rotate_right() {
-        let mut guard = Guard{x: 0, y: 0, direction: GuardDirection::Up};
-        guard.rotate_right();
-        assert_eq!(guard.direction, GuardDirection::Right);
-        let mut guard = Guard{x: 0, y: 0, direction: GuardDirection::Right};
-        guard.rotate_right();
-        assert_eq!(guard.direction, GuardDirection::Down);
-        let mut guard = Guard{x: 0, y: 0, direction: GuardDirection::Down};
-        guard.rotate_right();
-        assert_eq!(guard.direction, GuardDirection::Left);
-        let mut guard = Guard{x: 0, y: 0, direction: GuardDirection::Left};
-        guard.rotate_right();
-        assert_eq!(guard.direction, GuardDirection::Up);
+    fn test_find_candidates() {
+        let index = ObstacleIndex::new(&[Obstruction{x: 1, y: 0}], 3, 3);
+        let guard = Guard{x: 1, y: 2, direction: GuardDirection::Up};
+        assert_eq!(
+            find_candidates(&guard, &index),
+            vec![
+                (Guard{x: 1, y: 2, direction: GuardDirection::Up}, Obstruction{x: 1, y: 1}),
+                (Guard{x: 1, y: 1, direction: GuardDirection::Right}, Obstruction{x: 2, y: 1}),
+            ],
+        );
     }
 
     #[test]
-    fn test_step_guard() {
-        let mut guard = Guard{x: 2, y: 2, direction: GuardDirection::Up};
-        guard.step();
-        assert_eq!(guard, Guard{x: 2, y: 1, direction: GuardDirection::Up});
-
-        let mut guard = Guard{x: 2, y: 2, direction: GuardDirection::Right};
-        guard.step();
-        assert_eq!(guard, Guard{x: 3, y: 2, direction: GuardDirection::Right});
-
-        let mut guard = Guard{x: 2, y: 2, direction: GuardDirection::Down};
-        guard.step();
-        assert_eq!(guard, Guard{x: 2, y: 3, direction: GuardDirection::Down});
-
-        let mut guard = Guard{x: 2, y: 2, direction: GuardDirection::Left};
-        guard.step();
-        assert_eq!(guard, Guard{x: 1, y: 2, direction: GuardDirection::Left});
+    fn test_count_loops_matches_full_grid() {
+        let (w, h) = (40, 40);
+        let mut rng = Lcg::new(2025);
+        let mut obstructions = vec![];
+        for y in 0..h {
+            for x in 0..w {
+                if rng.below(10) == 0 && (x, y) != (20, 20) {
+                    obstructions.push(Obstruction{x, y});
+                }
+            }
+        }
+        let guard = Guard{x: 20, y: 20, direction: GuardDirection::Up};
+        let mut index = ObstacleIndex::new(&obstructions, w, h);
+        let mut expected = 0;
+        for y in 0..h {
+            for x in 0..w {
+                if obstructions.contains(&Obstruction{x, y}) || (x, y) == (guard.x, guard.y) {
+                    continue;
+                }
+                index.insert(x, y);
+                if is_loop(&guard, &index) {
+                    expected += 1;
+                }
+                index.remove(x, y);
+            }
+        }
+        assert!(!is_loop(&guard, &index));
+        assert_eq!(expected, 6);
+        assert_eq!(count_loops(&guard, &index), expected);
     }
 
     #[test]
-    fn test_is_guard_obstructed() {
-        let obstructions = vec![
-            Obstruction{x: 2, y: 1}
-        ];
-        let guard = Guard{x: 2, y: 2, direction: GuardDirection::Up};
-        assert!(guard.is_obstructed(&obstructions));
-
-        let obstructions = vec![
-            Obstruction{x: 3, y: 2}
-        ];
-        let guard = Guard{x: 2, y: 2, direction: GuardDirection::Right};
-        assert!(guard.is_obstructed(&obstructions));
-
-        let obstructions = vec![
-            Obstruction{x: 2, y: 3}
-        ];
-        let guard = Guard{x: 2, y: 2, direction: GuardDirection::Down};
-        assert!(guard.is_obstructed(&obstructions));
-
-        let obstructions = vec![
-            Obstruction{x: 1, y: 2}
-        ];
-        let guard = Guard{x: 2, y: 2, direction: GuardDirection::Left};
-        assert!(guard.is_obstructed(&obstructions));
-
-        let obstructions = vec![
-            Obstruction{x: 2, y: 2}
-        ];
-        let guard = Guard{x: 4, y: 4, direction: GuardDirection::Up};
-        assert!(!guard.is_obstructed(&obstructions));
-
-        let obstructions = vec![
-            Obstruction{x: 2, y: 2}
-        ];
-        let guard = Guard{x: 4, y: 4, direction: GuardDirection::Right};
-        assert!(!guard.is_obstructed(&obstructions));
-
-        let obstructions = vec![
-            Obstruction{x: 2, y: 2}
-        ];
-        let guard = Guard{x: 4, y: 4, direction: GuardDirection::Down};
-        assert!(!guard.is_obstructed(&obstructions));
-
-        let obstructions = vec![
-            Obstruction{x: 2, y: 2}
-        ];
-        let guard = Guard{x: 4, y: 4, direction: GuardDirection::Left};
-        assert!(!guard.is_obstructed(&obstructions));
+    fn test_find_loop_obstructions() {
+        let input = [
+            "....#.....",
+            ".........#",
+            "..........",
+            "..#.......",
+            ".......#..",
+            "..........",
+            ".#..^.....",
+            "........#.",
+            "#.........",
+            "......#...",
+        ].join("\n");
+        let (guard, obstructions, w, h) = parse_input(&input);
+        let index = ObstacleIndex::new(&obstructions, w, h);
+        let loops = find_loop_obstructions(&guard, &index);
+        let mut positions: Vec<(usize, usize)> = loops.iter()
+            .map(|(_, obstruction)| (obstruction.x, obstruction.y))
+            .collect();
+        positions.sort();
+        assert_eq!(positions, vec![(1, 8), (3, 6), (3, 8), (6, 7), (7, 7), (7, 9)]);
+
+        let (state, obstruction) = loops.iter().find(|(_, obstruction)| *obstruction == Obstruction{x: 3, y: 6}).unwrap();
+        let found_loop = &trace_loop(state, *obstruction, &index);
+        assert_eq!(found_loop.cycle[0], Guard{x: 4, y: 6, direction: GuardDirection::Left});
+        assert_eq!(found_loop.cycle.len(), 22);
+        assert_eq!(
+            render_loop(&guard, &index, found_loop),
+            [
+                "....#.....",
+                "....+---+#",
+                "....|...|.",
+                "..#.|...|.",
+                "....|..#|.",
+                "....|...|.",
+                ".#.O^---+.",
+                "........#.",
+                "#.........",
+                "......#...",
+            ].join("\n"),
+        );
     }
 }
@@ -1,53 +1,18 @@
 use std::fs;
 
+use crate::star_nineteen::{measure_trailheads, parse_input, Position, DIRECTIONS};
+
 pub fn run() {
     let file = fs::read_to_string("./inputs/star_twenty.txt").unwrap();
     let (positions, w, h) = parse_input(&file);
-    let result = calculate_total_map_score(&positions, w, h);
+    let result: usize = measure_trailheads(&positions, w, h).iter()
+        .map(|(_, metrics)| metrics.rating)
+        .sum();
 
     println!("Result: {}", result);
 }
 
-const DIRECTIONS: [(isize, isize); 4] = [
-    (0, -1), // Up
-    (0, 1), // Down
-    (-1, 0), // Left
-    (1, 0), // Right
-];
-
-#[derive(Copy, Clone, PartialEq, Debug, Eq, Hash)]
-struct Position {
-    x: isize,
-    y: isize,
-    h: isize,
-}
-
-fn parse_input(input: &String) -> (Vec<Position>, usize, usize) {
-
-    let w = input.lines().next().unwrap().len();
-    let h = input.lines().count();
-
-    let mut input = input.clone();
-    input.retain(|s| !s.is_whitespace());
-
-    let mut positions = vec![];
-
-    let mut x = 0;
-    let mut y = 0;
-    for found_char in input.chars() {
-        let h = found_char.to_string().parse::<isize>().unwrap();
-        positions.push(Position{x, y, h});
-        x += 1;
-        if x as usize >= w {
-            x = 0;
-            y += 1;
-        }
-    }
-
-    (positions, w, h)
-}
-
-fn calculate_trailhead_score(current_position: &Position, positions: &Vec<Position>, w: usize, h: usize) -> usize {
+pub(crate) fn calculate_trailhead_score(current_position: &Position, positions: &Vec<Position>, w: usize, h: usize) -> usize {
     if current_position.h >= 9 {
         return 1;
     }
@@ -72,7 +37,7 @@ fn calculate_trailhead_score(current_position: &Position, positions: &Vec<Positi
     count
 }
 
-fn calculate_total_map_score(positions: &Vec<Position>, w: usize, h: usize) -> usize {
+pub(crate) fn calculate_total_map_score(positions: &Vec<Position>, w: usize, h: usize) -> usize {
     positions.iter()
         .filter(|p| p.h == 0)
         .map(|p| calculate_trailhead_score(p, positions, w, h))